@@ -0,0 +1,243 @@
+use std::error::Error;
+use std::time::Duration;
+
+use async_openai::{Client, config::OpenAIConfig, types::{AssistantObject, AssistantTools, AssistantToolsFunction, CreateAssistantRequestArgs, CreateMessageRequestArgs, CreateRunRequestArgs, CreateThreadRequestArgs, MessageContent, RunObject, RunStatus, SubmitToolOutputsRunRequest, ThreadObject, ToolsOutputs}};
+
+use super::{Backend, RunState, ToolCall, ToolOutput, WriteText};
+
+/// The OpenAI Assistants flow: a remote assistant and thread, with runs polled until they settle.
+pub struct AssistantsBackend {
+    client: Client<OpenAIConfig>,
+    model: String,
+    assistant: Option<AssistantObject>,
+    thread: Option<ThreadObject>,
+    run: Option<RunObject>,
+}
+
+impl AssistantsBackend {
+    pub fn new(client: Client<OpenAIConfig>, model: &str) -> Self {
+        AssistantsBackend {
+            client,
+            model: model.to_string(),
+            assistant: None,
+            thread: None,
+            run: None,
+        }
+    }
+
+    fn thread_id(&self) -> Result<&str, Box<dyn Error>> {
+        match self.thread {
+            Some(ref thread) => Ok(&thread.id),
+            None => Err("no session created".into()),
+        }
+    }
+
+    fn run_id(&self) -> Result<&str, Box<dyn Error>> {
+        match self.run {
+            Some(ref run) => Ok(&run.id),
+            None => Err("no run started".into()),
+        }
+    }
+}
+
+impl Backend for AssistantsBackend {
+    async fn create_session(&mut self) -> Result<(), Box<dyn Error>> {
+        let thread_request = CreateThreadRequestArgs::default()
+            .build()?;
+        self.thread = Some(self.client.threads().create(thread_request).await?);
+
+        let tools = super::tools().into_iter()
+            .map(|function| AssistantTools::Function(AssistantToolsFunction{
+                r#type: "function".to_string(),
+                function,
+            }))
+            .collect::<Vec<AssistantTools>>();
+        let assistant_request = CreateAssistantRequestArgs::default()
+            .name(super::NAME)
+            .model(&self.model)
+            .description(super::DESCRIPTION)
+            .instructions(super::INSTRUCTIONS)
+            .tools(tools)
+            .build()?;
+        self.assistant = Some(self.client.assistants().create(assistant_request).await?);
+        Ok(())
+    }
+
+    async fn send_user_message(&mut self, content: &str) -> Result<(), Box<dyn Error>> {
+        let message = CreateMessageRequestArgs::default()
+            .role("user")
+            .content(content)
+            .build()?;
+        self.client.threads().messages(self.thread_id()?).create(message).await?;
+        Ok(())
+    }
+
+    async fn run(&mut self) -> Result<(), Box<dyn Error>> {
+        let assistant_id = match self.assistant {
+            Some(ref assistant) => assistant.id.clone(),
+            None => return Err("no session created".into()),
+        };
+        let run_request = CreateRunRequestArgs::default()
+            .assistant_id(assistant_id)
+            .build()?;
+        self.run = Some(self.client.threads().runs(self.thread_id()?).create(run_request).await?);
+        Ok(())
+    }
+
+    async fn poll(&mut self) -> Result<RunState, Box<dyn Error>> {
+        // The Assistants API has no push notifications, so give the run a moment between polls
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let run = self.client.threads().runs(self.thread_id()?).retrieve(self.run_id()?).await?;
+        let state = match run.status {
+            RunStatus::Queued => RunState::Queued,
+            RunStatus::InProgress => RunState::InProgress,
+            RunStatus::RequiresAction => {
+                let tool_calls = match run.required_action {
+                    Some(ref action) => action.submit_tool_outputs.tool_calls.iter()
+                        .map(|call| ToolCall{
+                            id: call.id.clone(),
+                            name: call.function.name.clone(),
+                            arguments: call.function.arguments.clone(),
+                        })
+                        .collect(),
+                    None => Vec::new(),
+                };
+                RunState::RequiresAction(tool_calls)
+            },
+            RunStatus::Completed => RunState::Completed,
+            RunStatus::Failed => RunState::Failed(match run.last_error {
+                Some(ref error) => error.message.clone(),
+                None => "run failed".to_string(),
+            }),
+            RunStatus::Cancelling | RunStatus::Cancelled => RunState::Cancelled,
+            RunStatus::Expired => RunState::Expired,
+        };
+        self.run = Some(run);
+        Ok(state)
+    }
+
+    async fn submit_tool_outputs(&mut self, outputs: Vec<ToolOutput>) -> Result<(), Box<dyn Error>> {
+        let request = SubmitToolOutputsRunRequest{
+            tool_outputs: outputs.into_iter()
+                .map(|output| ToolsOutputs{
+                    tool_call_id: Some(output.tool_call_id),
+                    output: Some(output.output),
+                })
+                .collect(),
+        };
+        self.client
+            .threads()
+            .runs(self.thread_id()?)
+            .submit_tool_outputs(self.run_id()?, request)
+            .await?;
+        Ok(())
+    }
+
+    // Runs of this version of the Assistants API can not be streamed, the reply is written whole
+    async fn stream_answer(&mut self, write: &mut WriteText<'_>) -> Result<(), Box<dyn Error>> {
+        //retrieve last message
+        let last_message = self.client
+            .threads()
+            .messages(self.thread_id()?)
+            .list(&[("limit", "1")])
+            .await?;
+        let last_message = match last_message.data.first() {
+            Some(message) => message,
+            None => return Err("thread has no messages".into()),
+        };
+        match last_message.content.first() {
+            Some(MessageContent::Text(text)) => write(&text.text.value),
+            _ => Err("last message was not text".into()),
+        }
+    }
+
+    async fn close(&mut self) -> Result<(), Box<dyn Error>> {
+        if let Some(assistant) = self.assistant.take() {
+            self.client.assistants().delete(&assistant.id).await?;
+        }
+        if let Some(thread) = self.thread.take() {
+            self.client.threads().delete(&thread.id).await?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn json(value: serde_json::Value) -> (&'static str, String) {
+        ("application/json", value.to_string())
+    }
+
+    fn run(status: &str, required_action: serde_json::Value) -> (&'static str, String) {
+        json(serde_json::json!({
+            "id": "run_1", "object": "thread.run", "created_at": 0, "thread_id": "thread_1", "assistant_id": "asst_1",
+            "status": status, "required_action": required_action, "last_error": null, "expires_at": null,
+            "started_at": null, "cancelled_at": null, "failed_at": null, "completed_at": null,
+            "model": "test", "instructions": "", "tools": [], "file_ids": [], "metadata": null,
+        }))
+    }
+
+    fn message(role: &str, text: &str) -> serde_json::Value {
+        serde_json::json!({
+            "id": "msg_1", "object": "thread.message", "created_at": 0, "thread_id": "thread_1", "role": role,
+            "content": [{"type": "text", "text": {"value": text, "annotations": []}}],
+            "assistant_id": null, "run_id": null, "file_ids": [], "metadata": null,
+        })
+    }
+
+    #[tokio::test]
+    async fn runs_are_mapped_to_run_states() {
+        let (base, requests) = fixtures::http_stub(vec![
+            json(serde_json::json!({"id": "thread_1", "object": "thread", "created_at": 0, "metadata": null})),
+            json(serde_json::json!({
+                "id": "asst_1", "object": "assistant", "created_at": 0, "name": "Data", "description": null,
+                "model": "test", "instructions": null, "tools": [], "file_ids": [], "metadata": null,
+            })),
+            json(message("user", "how many?")),
+            run("queued", serde_json::Value::Null),
+            run("requires_action", serde_json::json!({"type": "submit_tool_outputs", "submit_tool_outputs": {"tool_calls": [
+                {"id": "call_1", "type": "function", "function": {"name": "db_query", "arguments": "{\"query\": \"SELECT 1\"}"}},
+            ]}})),
+            run("in_progress", serde_json::Value::Null),
+            json(serde_json::json!({"object": "list", "data": [message("assistant", "Database Queried")],
+                "first_id": "msg_1", "last_id": "msg_1", "has_more": false})),
+        ]).await;
+        let mut backend = AssistantsBackend::new(Client::with_config(OpenAIConfig::new().with_api_base(base)), "test");
+        backend.create_session().await.unwrap();
+        backend.send_user_message("how many?").await.unwrap();
+        backend.run().await.unwrap();
+
+        assert_eq!(backend.poll().await.unwrap(), RunState::RequiresAction(vec![ToolCall{
+            id: "call_1".to_string(),
+            name: "db_query".to_string(),
+            arguments: "{\"query\": \"SELECT 1\"}".to_string(),
+        }]));
+        backend.submit_tool_outputs(vec![ToolOutput{ tool_call_id: "call_1".to_string(), output: "1".to_string() }]).await.unwrap();
+        let mut answer = String::new();
+        backend.stream_answer(&mut |text| { answer.push_str(text); Ok(()) }).await.unwrap();
+        assert_eq!(answer, "Database Queried");
+
+        let requests = requests.await.unwrap();
+        let paths = requests.iter().map(|(path, _)| path.as_str()).collect::<Vec<&str>>();
+        assert_eq!(paths, vec![
+            "POST /v1/threads",
+            "POST /v1/assistants",
+            "POST /v1/threads/thread_1/messages",
+            "POST /v1/threads/thread_1/runs",
+            "GET /v1/threads/thread_1/runs/run_1",
+            "POST /v1/threads/thread_1/runs/run_1/submit_tool_outputs",
+            "GET /v1/threads/thread_1/messages?limit=1",
+        ]);
+        let assistant: serde_json::Value = serde_json::from_str(&requests[1].1).unwrap();
+        assert_eq!(assistant["instructions"], crate::backend::INSTRUCTIONS);
+        let message: serde_json::Value = serde_json::from_str(&requests[2].1).unwrap();
+        assert_eq!(message["role"], "user");
+        assert_eq!(message["content"], "how many?");
+        let outputs: serde_json::Value = serde_json::from_str(&requests[5].1).unwrap();
+        assert_eq!(outputs["tool_outputs"][0]["tool_call_id"], "call_1");
+        assert_eq!(outputs["tool_outputs"][0]["output"], "1");
+    }
+}
@@ -0,0 +1,269 @@
+use std::error::Error;
+
+use async_openai::{Client, config::OpenAIConfig, types::{ChatCompletionMessageToolCall, ChatCompletionMessageToolCallChunk, ChatCompletionRequestAssistantMessageArgs, ChatCompletionRequestMessage, ChatCompletionRequestSystemMessageArgs, ChatCompletionRequestToolMessageArgs, ChatCompletionRequestUserMessageArgs, ChatCompletionTool, ChatCompletionToolType, CreateChatCompletionRequestArgs, FinishReason, FunctionCall}};
+use futures::StreamExt;
+
+use super::{Backend, RunState, ToolCall, ToolOutput, WriteText};
+
+/// The Chat Completions flow: the conversation is kept locally and resent on every request.
+/// This works with any OpenAI-compatible server, set `OPENAI_API_BASE` to point at it.
+/// Replies are streamed, but only handed on once the model has finished: text written before
+/// a tool call is not an answer, and which of the two it is only shows at the end.
+pub struct ChatBackend {
+    client: Client<OpenAIConfig>,
+    model: String,
+    messages: Vec<ChatCompletionRequestMessage>,
+    // Set when the model has new input it has not responded to yet
+    pending: bool,
+    // The text of the last reply, once the model has answered without calling tools
+    answer: Option<String>,
+}
+
+impl ChatBackend {
+    pub fn new(client: Client<OpenAIConfig>, model: &str) -> Self {
+        ChatBackend {
+            client,
+            model: model.to_string(),
+            messages: Vec::new(),
+            pending: false,
+            answer: None,
+        }
+    }
+
+    async fn complete(&mut self) -> Result<RunState, Box<dyn Error>> {
+        let tools = super::tools().into_iter()
+            .map(|function| ChatCompletionTool{
+                r#type: ChatCompletionToolType::Function,
+                function,
+            })
+            .collect::<Vec<ChatCompletionTool>>();
+        let request = CreateChatCompletionRequestArgs::default()
+            .model(&self.model)
+            .messages(self.messages.clone())
+            .tools(tools)
+            .build()?;
+        let mut stream = self.client.chat().create_stream(request).await?;
+
+        let mut content = String::new();
+        let mut tool_calls: Vec<ChatCompletionMessageToolCall> = Vec::new();
+        let mut finish_reason = None;
+        while let Some(response) = stream.next().await {
+            let choice = match response?.choices.into_iter().next() {
+                Some(choice) => choice,
+                None => continue,
+            };
+            for chunk in choice.delta.tool_calls.unwrap_or_default() {
+                add_tool_call_chunk(&mut tool_calls, chunk);
+            }
+            content.push_str(&choice.delta.content.unwrap_or_default());
+            finish_reason = choice.finish_reason.or(finish_reason);
+        }
+
+        match finish_reason {
+            Some(FinishReason::ContentFilter) => return Ok(RunState::Failed("the reply was blocked by the content filter".to_string())),
+            // Some servers end with `stop` even after calling tools, so the calls decide
+            _ if tool_calls.is_empty() => {
+                self.answer = Some(content);
+                return Ok(RunState::Completed);
+            },
+            _ => {},
+        }
+        // The calls become part of the history so tool outputs can refer back to them,
+        // along with anything the model wrote before making them
+        let mut reply = ChatCompletionRequestAssistantMessageArgs::default();
+        reply.tool_calls(tool_calls.clone());
+        if !content.is_empty() {
+            reply.content(content);
+        }
+        self.messages.push(ChatCompletionRequestMessage::Assistant(reply.build()?));
+        Ok(RunState::RequiresAction(tool_calls.into_iter().map(to_tool_call).collect()))
+    }
+}
+
+/// Streamed tool calls arrive in pieces: the id and name first, then the arguments a few characters at a time.
+fn add_tool_call_chunk(tool_calls: &mut Vec<ChatCompletionMessageToolCall>, chunk: ChatCompletionMessageToolCallChunk) {
+    let index = chunk.index.max(0) as usize;
+    while tool_calls.len() <= index {
+        tool_calls.push(ChatCompletionMessageToolCall{
+            id: String::new(),
+            r#type: ChatCompletionToolType::Function,
+            function: FunctionCall{ name: String::new(), arguments: String::new() },
+        });
+    }
+    let call = &mut tool_calls[index];
+    if let Some(id) = chunk.id {
+        call.id.push_str(&id);
+    }
+    if let Some(function) = chunk.function {
+        call.function.name.push_str(&function.name.unwrap_or_default());
+        call.function.arguments.push_str(&function.arguments.unwrap_or_default());
+    }
+}
+
+fn to_tool_call(call: ChatCompletionMessageToolCall) -> ToolCall {
+    ToolCall{
+        id: call.id,
+        name: call.function.name,
+        arguments: call.function.arguments,
+    }
+}
+
+impl Backend for ChatBackend {
+    async fn create_session(&mut self) -> Result<(), Box<dyn Error>> {
+        let system = ChatCompletionRequestSystemMessageArgs::default()
+            .content(super::INSTRUCTIONS)
+            .build()?;
+        self.messages = vec![ChatCompletionRequestMessage::System(system)];
+        Ok(())
+    }
+
+    async fn send_user_message(&mut self, content: &str) -> Result<(), Box<dyn Error>> {
+        let message = ChatCompletionRequestUserMessageArgs::default()
+            .content(content)
+            .build()?;
+        self.messages.push(ChatCompletionRequestMessage::User(message));
+        Ok(())
+    }
+
+    async fn run(&mut self) -> Result<(), Box<dyn Error>> {
+        self.pending = true;
+        self.answer = None;
+        Ok(())
+    }
+
+    async fn poll(&mut self) -> Result<RunState, Box<dyn Error>> {
+        if !self.pending {
+            return Ok(match self.answer {
+                Some(_) => RunState::Completed,
+                None => RunState::Failed("no run started".to_string()),
+            });
+        }
+        self.pending = false;
+        self.complete().await
+    }
+
+    async fn submit_tool_outputs(&mut self, outputs: Vec<ToolOutput>) -> Result<(), Box<dyn Error>> {
+        for output in outputs {
+            let message = ChatCompletionRequestToolMessageArgs::default()
+                .tool_call_id(output.tool_call_id)
+                .content(output.output)
+                .build()?;
+            self.messages.push(ChatCompletionRequestMessage::Tool(message));
+        }
+        self.pending = true;
+        Ok(())
+    }
+
+    async fn stream_answer(&mut self, write: &mut WriteText<'_>) -> Result<(), Box<dyn Error>> {
+        let answer = match self.answer.take() {
+            Some(answer) => answer,
+            None => return Err("run has not completed".into()),
+        };
+        write(&answer)?;
+
+        // The reply becomes part of the history so later questions can refer back to it
+        let reply = ChatCompletionRequestAssistantMessageArgs::default()
+            .content(answer)
+            .build()?;
+        self.messages.push(ChatCompletionRequestMessage::Assistant(reply));
+        Ok(())
+    }
+
+    async fn close(&mut self) -> Result<(), Box<dyn Error>> {
+        self.messages.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    fn chunk(delta: &str, finish_reason: &str) -> String {
+        format!("data: {{\"id\": \"chat_1\", \"object\": \"chat.completion.chunk\", \"created\": 0, \"model\": \"test\", \
+            \"choices\": [{{\"index\": 0, \"delta\": {}, \"finish_reason\": {}}}]}}\n\n", delta, finish_reason)
+    }
+
+    // A streamed reply as server-sent events, one chunk per delta and a last one with the finish reason
+    fn events(deltas: &[&str], finish_reason: &str) -> (&'static str, String) {
+        let mut body = deltas.iter().map(|delta| chunk(delta, "null")).collect::<String>();
+        body.push_str(&chunk("{}", &format!("\"{}\"", finish_reason)));
+        body.push_str("data: [DONE]\n\n");
+        ("text/event-stream", body)
+    }
+
+    fn backend(base: String) -> ChatBackend {
+        ChatBackend::new(Client::with_config(OpenAIConfig::new().with_api_base(base)), "test")
+    }
+
+    #[tokio::test]
+    async fn tool_calls_and_answers_are_read_from_the_stream() {
+        let (base, requests) = fixtures::http_stub(vec![
+            events(&[
+                r#"{"role": "assistant", "tool_calls": [{"index": 0, "id": "call_1", "type": "function", "function": {"name": "db_query", "arguments": ""}}]}"#,
+                r#"{"tool_calls": [{"index": 0, "function": {"arguments": "{\"query\": "}}]}"#,
+                r#"{"tool_calls": [{"index": 0, "function": {"arguments": "\"SELECT 1\"}"}}]}"#,
+            ], "tool_calls"),
+            events(&[r#"{"role": "assistant", "content": "Database "}"#, r#"{"content": "Queried"}"#], "stop"),
+        ]).await;
+        let mut backend = backend(base);
+        backend.create_session().await.unwrap();
+        backend.send_user_message("how many?").await.unwrap();
+        backend.run().await.unwrap();
+
+        let state = backend.poll().await.unwrap();
+        assert_eq!(state, RunState::RequiresAction(vec![ToolCall{
+            id: "call_1".to_string(),
+            name: "db_query".to_string(),
+            arguments: "{\"query\": \"SELECT 1\"}".to_string(),
+        }]));
+        backend.submit_tool_outputs(vec![ToolOutput{ tool_call_id: "call_1".to_string(), output: "1".to_string() }]).await.unwrap();
+        assert_eq!(backend.poll().await.unwrap(), RunState::Completed);
+        let mut pieces = Vec::new();
+        backend.stream_answer(&mut |text| { pieces.push(text.to_string()); Ok(()) }).await.unwrap();
+        assert_eq!(pieces, vec!["Database Queried"]);
+
+        let requests = requests.await.unwrap();
+        assert_eq!(requests[0].0, "POST /v1/chat/completions");
+        let first: serde_json::Value = serde_json::from_str(&requests[0].1).unwrap();
+        assert_eq!(first["model"], "test");
+        assert_eq!(first["stream"], true);
+        assert_eq!(first["messages"][1]["content"], "how many?");
+        assert!(first["tools"].as_array().unwrap().iter().any(|tool| tool["function"]["name"] == "db_query"));
+        let second: serde_json::Value = serde_json::from_str(&requests[1].1).unwrap();
+        let messages = second["messages"].as_array().unwrap();
+        assert_eq!(messages[2]["tool_calls"][0]["id"], "call_1");
+        assert_eq!(messages[3]["role"], "tool");
+        assert_eq!(messages[3]["tool_call_id"], "call_1");
+        assert_eq!(messages[3]["content"], "1");
+        assert_eq!(backend.messages.len(), 5);
+    }
+
+    #[tokio::test]
+    async fn text_before_a_tool_call_is_not_the_answer() {
+        let (base, requests) = fixtures::http_stub(vec![
+            events(&[
+                r#"{"role": "assistant", "content": "Let me look that up."}"#,
+                r#"{"tool_calls": [{"index": 0, "id": "call_1", "type": "function", "function": {"name": "db_query", "arguments": "{\"query\": \"SELECT 1\"}"}}]}"#,
+            ], "tool_calls"),
+            events(&[r#"{"role": "assistant", "content": "There is 1."}"#], "stop"),
+        ]).await;
+        let mut backend = backend(base);
+        backend.create_session().await.unwrap();
+        backend.send_user_message("how many?").await.unwrap();
+        backend.run().await.unwrap();
+
+        assert!(matches!(backend.poll().await.unwrap(), RunState::RequiresAction(calls) if calls[0].id == "call_1"));
+        backend.submit_tool_outputs(vec![ToolOutput{ tool_call_id: "call_1".to_string(), output: "1".to_string() }]).await.unwrap();
+        assert_eq!(backend.poll().await.unwrap(), RunState::Completed);
+        let mut answer = String::new();
+        backend.stream_answer(&mut |text| { answer.push_str(text); Ok(()) }).await.unwrap();
+        assert_eq!(answer, "There is 1.");
+
+        let requests = requests.await.unwrap();
+        let second: serde_json::Value = serde_json::from_str(&requests[1].1).unwrap();
+        assert_eq!(second["messages"][2]["content"], "Let me look that up.");
+        assert_eq!(second["messages"][2]["tool_calls"][0]["id"], "call_1");
+    }
+}
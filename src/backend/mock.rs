@@ -0,0 +1,126 @@
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fs;
+
+use serde_json::Value;
+
+use super::{Backend, RunState, ToolCall, ToolOutput, WriteText};
+
+/// An offline backend that replays a fixed script of run states.
+/// Everything sent to it is recorded so the `data` command can be checked without a network.
+#[derive(Default)]
+pub struct MockBackend {
+    // States handed out by `poll`, in order; an empty script completes immediately
+    script: VecDeque<RunState>,
+    // Replies handed out by `stream_answer`, in order; once empty the last user message is echoed
+    answers: VecDeque<String>,
+    pub messages: Vec<String>,
+    pub tool_outputs: Vec<ToolOutput>,
+    pub runs: usize,
+}
+
+impl MockBackend {
+    pub fn new(script: Vec<RunState>, answers: Vec<String>) -> Self {
+        MockBackend {
+            script: script.into(),
+            answers: answers.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Loads a script from a JSON file holding an array of run states, for example
+    /// `[{"status": "requires_action", "tool_calls": [{"id": "call_1", "name": "db_query",
+    /// "arguments": {"query": "SELECT 1"}}]}, {"status": "completed", "answer": "done"}]`
+    pub fn from_file(path: &str) -> Result<Self, Box<dyn Error>> {
+        let script: Value = serde_json::from_str(&fs::read_to_string(path)?)?;
+        let steps = match script.as_array() {
+            Some(steps) => steps,
+            None => return Err(format!("{}: mock script must be a JSON array", path).into()),
+        };
+
+        let mut states = Vec::new();
+        let mut answers = Vec::new();
+        for step in steps {
+            let state = match step["status"].as_str() {
+                Some("queued") => RunState::Queued,
+                Some("in_progress") => RunState::InProgress,
+                Some("requires_action") => RunState::RequiresAction(parse_tool_calls(&step["tool_calls"])?),
+                Some("completed") => {
+                    if let Some(answer) = step["answer"].as_str() {
+                        answers.push(answer.to_string());
+                    }
+                    RunState::Completed
+                },
+                Some("failed") => RunState::Failed(step["reason"].as_str().unwrap_or("run failed").to_string()),
+                Some("cancelled") => RunState::Cancelled,
+                Some("expired") => RunState::Expired,
+                _ => return Err(format!("{}: unknown status in step {}", path, step).into()),
+            };
+            states.push(state);
+        }
+        Ok(MockBackend::new(states, answers))
+    }
+}
+
+fn parse_tool_calls(tool_calls: &Value) -> Result<Vec<ToolCall>, Box<dyn Error>> {
+    let tool_calls = match tool_calls.as_array() {
+        Some(tool_calls) => tool_calls,
+        None => return Err("requires_action step needs a tool_calls array".into()),
+    };
+    tool_calls.iter()
+        .map(|call| {
+            let (id, name) = match (call["id"].as_str(), call["name"].as_str()) {
+                (Some(id), Some(name)) => (id, name),
+                _ => return Err(format!("tool call {} needs an id and a name", call).into()),
+            };
+            // Arguments may be written as a JSON object for readability, the model sends a string
+            let arguments = match call["arguments"] {
+                Value::String(ref arguments) => arguments.clone(),
+                ref arguments => arguments.to_string(),
+            };
+            Ok(ToolCall{
+                id: id.to_string(),
+                name: name.to_string(),
+                arguments,
+            })
+        })
+        .collect()
+}
+
+impl Backend for MockBackend {
+    async fn create_session(&mut self) -> Result<(), Box<dyn Error>> {
+        self.messages.clear();
+        self.tool_outputs.clear();
+        Ok(())
+    }
+
+    async fn send_user_message(&mut self, content: &str) -> Result<(), Box<dyn Error>> {
+        self.messages.push(content.to_string());
+        Ok(())
+    }
+
+    async fn run(&mut self) -> Result<(), Box<dyn Error>> {
+        self.runs += 1;
+        Ok(())
+    }
+
+    async fn poll(&mut self) -> Result<RunState, Box<dyn Error>> {
+        Ok(self.script.pop_front().unwrap_or(RunState::Completed))
+    }
+
+    async fn submit_tool_outputs(&mut self, outputs: Vec<ToolOutput>) -> Result<(), Box<dyn Error>> {
+        self.tool_outputs.extend(outputs);
+        Ok(())
+    }
+
+    async fn stream_answer(&mut self, write: &mut WriteText<'_>) -> Result<(), Box<dyn Error>> {
+        match self.answers.pop_front() {
+            Some(answer) => write(&answer),
+            None => write(&format!("mock: {}", self.messages.last().map(String::as_str).unwrap_or(""))),
+        }
+    }
+
+    async fn close(&mut self) -> Result<(), Box<dyn Error>> {
+        Ok(())
+    }
+}
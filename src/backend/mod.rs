@@ -0,0 +1,237 @@
+use std::error::Error;
+
+use async_openai::types::ChatCompletionFunctions;
+use serde_json::Value;
+
+mod assistants;
+mod chat;
+mod mock;
+pub use assistants::AssistantsBackend;
+pub use chat::ChatBackend;
+pub use mock::MockBackend;
+
+/// The state of an assistant run, as seen by the `data` command.
+/// Every backend maps its own notion of progress onto these states.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RunState {
+    Queued,
+    InProgress,
+    // The assistant wants one or more tools called before it can continue
+    RequiresAction(Vec<ToolCall>),
+    Completed,
+    Failed(String),
+    Cancelled,
+    Expired,
+}
+
+/// A single function call requested by the assistant.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolCall {
+    pub id: String,
+    pub name: String,
+    // Raw JSON arguments, exactly as generated by the model
+    pub arguments: String,
+}
+
+/// The result of a tool call, sent back to the assistant.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolOutput {
+    pub tool_call_id: String,
+    pub output: String,
+}
+
+/// Where `Backend::stream_answer` sends the text of a reply.
+pub type WriteText<'a> = dyn FnMut(&str) -> Result<(), Box<dyn Error>> + 'a;
+
+/// A conversation with an LLM that can call the tools returned by `tools()`.
+///
+/// The expected flow for one question is:
+/// `send_user_message` -> `run` -> `poll` until the run settles, answering any
+/// `RunState::RequiresAction` with `submit_tool_outputs` -> `stream_answer`.
+pub trait Backend {
+    /// Starts a new conversation, registering the instructions and tools.
+    async fn create_session(&mut self) -> Result<(), Box<dyn Error>>;
+
+    /// Appends a user message to the conversation without running the model.
+    async fn send_user_message(&mut self, content: &str) -> Result<(), Box<dyn Error>>;
+
+    /// Asks the model to respond to the conversation so far, with tools available.
+    async fn run(&mut self) -> Result<(), Box<dyn Error>>;
+
+    /// Returns the current state of the run started by `run`.
+    async fn poll(&mut self) -> Result<RunState, Box<dyn Error>>;
+
+    /// Answers the tool calls of a `RunState::RequiresAction` and resumes the run.
+    async fn submit_tool_outputs(&mut self, outputs: Vec<ToolOutput>) -> Result<(), Box<dyn Error>>;
+
+    /// Hands the text of the model's reply to `write` once the run has completed,
+    /// piece by piece as it arrives where the backend can stream it.
+    async fn stream_answer(&mut self, write: &mut WriteText<'_>) -> Result<(), Box<dyn Error>>;
+
+    /// Releases anything the session holds on the remote side.
+    async fn close(&mut self) -> Result<(), Box<dyn Error>>;
+}
+
+pub const NAME: &str = "Data";
+pub const DESCRIPTION: &str = "An sql assistant that can help explore sqlite databases.";
+pub const INSTRUCTIONS: &str = "
+FOLLOW THESE INSTUCTIONS PRECISELY:
+you are an AI designed to help people explore sqlite databases.
+    you are an expert at sqlite.
+
+    you will be given a question from the user,
+    you can query the database,
+    you can respond to questions from the user which may not require a database query
+
+    This assistant can connect to a database, list the tables and columns in the database, and execute queries on the database.
+    DO NOT summarize or display any data yourself after a database request has been made,
+        ONLY respond with \"Database Queried\".
+    DO NOT create a new message after a function call
+
+    you have complete access to the database, and can answer any query about it.
+    you have the ability to remember infomration from previous queries
+    you have the ability to recall data you've seen before without querying the database
+
+    IF the user tells you not to query the database:
+        you are still able to provid information about the database
+        use your ability to recall information to attempt to answer the question
+
+    IF you were only sent the table index of a database:
+        DO call describe_table or search_columns to learn about the tables you need before querying them
+
+    IF the database queries successfully:
+        ONLY respond with \"Database Queried\"
+
+    IF the user enters a query whos parameters may be invaild given the database:
+        DO NOT run the query
+        DO explain to the user why the query might be invalid
+
+    IF a database query fails:
+        you will recieve the error from sqlite, the query that failed and the number of retries left
+        IF retries_left is more than 0:
+            DO correct the query using the error and run it again
+        OTHERWISE:
+            DO NOT attempt any more corrections
+            DO explain the error to the user
+
+    IF you recieve an empty result from a database query:
+        the query ran but no rows matched
+        DO explain to the user why no rows might have matched";
+
+/// The functions the assistant is allowed to call.
+pub fn tools() -> Vec<ChatCompletionFunctions> {
+    vec![
+        ChatCompletionFunctions{
+            name:"db_query".to_string(),
+            description:Some("generate a sqlite query to retrieve data requested by the user,
+                this data will be printed to the user, you do not need to summarize it.".to_string()),
+            parameters:serde_json::from_str::<Value>("{
+                \"type\": \"object\",
+                \"properties\": {
+                    \"query\": {
+                        \"type\": \"string\",
+                        \"description\": \"the query to be executed\"
+                    },
+                    \"database\": {
+                        \"type\": \"string\",
+                        \"description\": \"the alias of the database to query, the active database is used when omitted\"
+                    }
+                },
+
+                \"required\": [\"query\"]
+            }").unwrap(),
+        },
+        //schema lookups, for databases too large to send whole
+        ChatCompletionFunctions{
+            name:"list_tables".to_string(),
+            description:Some("list the tables and views in a database with their number of columns".to_string()),
+            parameters:serde_json::from_str::<Value>("{
+                \"type\": \"object\",
+                \"properties\": {
+                    \"pattern\": {
+                        \"type\": \"string\",
+                        \"description\": \"only list tables whose name contains this, ignoring case\"
+                    },
+                    \"database\": {
+                        \"type\": \"string\",
+                        \"description\": \"the alias of the database, the active database is used when omitted\"
+                    }
+                }
+            }").unwrap(),
+        },
+        ChatCompletionFunctions{
+            name:"describe_table".to_string(),
+            description:Some("describe the columns, keys, indexes, row count and sample rows of a table or view".to_string()),
+            parameters:serde_json::from_str::<Value>("{
+                \"type\": \"object\",
+                \"properties\": {
+                    \"table\": {
+                        \"type\": \"string\",
+                        \"description\": \"the name of the table, written as schema.table for attached databases\"
+                    },
+                    \"database\": {
+                        \"type\": \"string\",
+                        \"description\": \"the alias of the database, the active database is used when omitted\"
+                    }
+                },
+
+                \"required\": [\"table\"]
+            }").unwrap(),
+        },
+        ChatCompletionFunctions{
+            name:"search_columns".to_string(),
+            description:Some("find columns in every table whose name contains a pattern, ignoring case".to_string()),
+            parameters:serde_json::from_str::<Value>("{
+                \"type\": \"object\",
+                \"properties\": {
+                    \"pattern\": {
+                        \"type\": \"string\",
+                        \"description\": \"part of the column name to look for\"
+                    },
+                    \"database\": {
+                        \"type\": \"string\",
+                        \"description\": \"the alias of the database, the active database is used when omitted\"
+                    }
+                },
+
+                \"required\": [\"pattern\"]
+            }").unwrap(),
+        },
+        //hello world
+        ChatCompletionFunctions{
+            name:"hello_world".to_string(),
+            description:Some("print hello world".to_string()),
+            parameters:serde_json::from_str::<Value>("{
+                \"type\": \"object\",
+                \"properties\": {
+                    \"name\": {
+                        \"type\": \"string\",
+                        \"description\": \"the name of the person to say hello to\"
+                    }
+                },
+
+                \"required\": [\"name\"]
+            }").unwrap(),
+        },
+        //connect to database
+        ChatCompletionFunctions{
+            name:"connect_to_database".to_string(),
+            description:Some("connect to a sqlite database, the user is asked to confirm and the new database becomes the active one".to_string()),
+            parameters:serde_json::from_str::<Value>("{
+                \"type\": \"object\",
+                \"properties\": {
+                    \"database\": {
+                        \"type\": \"string\",
+                        \"description\": \"the path to the database to connect to, relative to the user's working directory\"
+                    },
+                    \"alias\": {
+                        \"type\": \"string\",
+                        \"description\": \"the name to refer to the database by, defaults to the file name\"
+                    }
+                },
+
+                \"required\": [\"database\"]
+            }").unwrap(),
+        },
+    ]
+}
@@ -0,0 +1,230 @@
+use std::env;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+use crate::keymap::KeyMap;
+
+/// Which LLM backend the `data` command talks to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BackendKind {
+    Assistants,
+    Chat,
+    Mock,
+}
+
+impl BackendKind {
+    pub fn parse(name: &str) -> Result<BackendKind, Box<dyn Error>> {
+        match name {
+            "assistants" => Ok(BackendKind::Assistants),
+            "chat" => Ok(BackendKind::Chat),
+            "mock" => Ok(BackendKind::Mock),
+            _ => Err(format!("unknown backend '{}', expected assistants, chat or mock", name).into()),
+        }
+    }
+}
+
+/// What the assistant is sent about a database when it is connected.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SchemaMode {
+    // The full schema for small databases, the table index for large ones
+    Auto,
+    Full,
+    // Only the table index, the assistant looks up the rest with the schema tools
+    Index,
+}
+
+impl SchemaMode {
+    pub fn parse(name: &str) -> Result<SchemaMode, Box<dyn Error>> {
+        match name {
+            "auto" => Ok(SchemaMode::Auto),
+            "full" => Ok(SchemaMode::Full),
+            "index" => Ok(SchemaMode::Index),
+            _ => Err(format!("unknown schema mode '{}', expected auto, full or index", name).into()),
+        }
+    }
+}
+
+/// Startup settings, read from the environment and overridden by command line flags.
+/// The line editor keys are read from the config file, `config.json` in the user's config directory.
+#[derive(Debug, Clone)]
+pub struct Config {
+    pub backend: BackendKind,
+    pub model: String,
+    // Base url of an OpenAI-compatible server, `None` uses api.openai.com
+    pub api_base: Option<String>,
+    pub api_key: Option<String>,
+    // JSON script replayed by the mock backend
+    pub mock_script: Option<String>,
+    // Limits on the query results sent back to the assistant per tool call
+    pub max_rows: usize,
+    pub token_budget: usize,
+    // Limit on the schema summary sent when a database is connected
+    pub schema_budget: usize,
+    pub schema_mode: SchemaMode,
+    // Let the assistant run statements that modify the database, each one confirmed by the user
+    pub allow_writes: bool,
+    // Show generated queries in the line editor before they run
+    pub approve_queries: bool,
+    // Failed queries the assistant may correct and rerun per question
+    pub sql_retries: usize,
+    // Send queries run with the `sql` command and their results to the assistant
+    pub share_sql: bool,
+    // Directories the assistant may open databases in as canonical paths, the working directory when none are given
+    pub allowed_dirs: Vec<PathBuf>,
+    // Where the lines typed at the prompt are kept between sessions, `None` keeps them in memory only
+    pub history_file: Option<PathBuf>,
+    pub history_size: usize,
+    // Keys of the line editor, a preset with the bindings of the config file on top
+    pub keymap: KeyMap,
+}
+
+impl Config {
+    pub fn load() -> Result<Config, Box<dyn Error>> {
+        let mut config = Config {
+            backend: match env::var("DATA_BACKEND") {
+                Ok(name) => BackendKind::parse(&name)?,
+                Err(_) => BackendKind::Assistants,
+            },
+            model: env::var("DATA_MODEL").unwrap_or_else(|_| "gpt-3.5-turbo-16k".to_string()),
+            api_base: env::var("OPENAI_API_BASE").ok(),
+            api_key: env::var("OPENAI_API_KEY").ok(),
+            mock_script: env::var("DATA_MOCK_SCRIPT").ok(),
+            max_rows: match env::var("DATA_MAX_ROWS") {
+                Ok(value) => parse_number("DATA_MAX_ROWS", &value)?,
+                Err(_) => 100,
+            },
+            token_budget: match env::var("DATA_TOKEN_BUDGET") {
+                Ok(value) => parse_number("DATA_TOKEN_BUDGET", &value)?,
+                Err(_) => 2000,
+            },
+            schema_budget: match env::var("DATA_SCHEMA_BUDGET") {
+                Ok(value) => parse_number("DATA_SCHEMA_BUDGET", &value)?,
+                Err(_) => 4000,
+            },
+            schema_mode: match env::var("DATA_SCHEMA_MODE") {
+                Ok(name) => SchemaMode::parse(&name)?,
+                Err(_) => SchemaMode::Auto,
+            },
+            allow_writes: env::var("DATA_ALLOW_WRITES").is_ok_and(|value| value == "1" || value == "true"),
+            approve_queries: env::var("DATA_APPROVE").is_ok_and(|value| value == "1" || value == "true"),
+            sql_retries: match env::var("DATA_SQL_RETRIES") {
+                Ok(value) => parse_number("DATA_SQL_RETRIES", &value)?,
+                Err(_) => 2,
+            },
+            share_sql: env::var("DATA_SHARE_SQL").is_ok_and(|value| value == "1" || value == "true"),
+            allowed_dirs: match env::var_os("DATA_ALLOWED_DIRS") {
+                Some(dirs) => env::split_paths(&dirs).collect(),
+                None => Vec::new(),
+            },
+            history_file: match env::var_os("DATA_HISTORY_FILE") {
+                Some(path) => Some(PathBuf::from(path)),
+                None => data_dir().map(|dir| dir.join("data_bot2").join("history")),
+            },
+            history_size: match env::var("DATA_HISTORY_SIZE") {
+                Ok(value) => parse_number("DATA_HISTORY_SIZE", &value)?,
+                Err(_) => 1000,
+            },
+            keymap: KeyMap::default(),
+        };
+        let mut config_file = env::var_os("DATA_CONFIG").map(PathBuf::from);
+        let mut keymap = env::var("DATA_KEYMAP").ok();
+
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or(format!("missing value for {}", arg));
+            match arg.as_str() {
+                "--backend" => config.backend = BackendKind::parse(&value()?)?,
+                "--model" => config.model = value()?,
+                "--api-base" => config.api_base = Some(value()?),
+                "--mock-script" => config.mock_script = Some(value()?),
+                "--write" => config.allow_writes = true,
+                "--approve" => config.approve_queries = true,
+                "--share-sql" => config.share_sql = true,
+                "--allow-dir" => config.allowed_dirs.push(PathBuf::from(value()?)),
+                "--max-rows" => config.max_rows = parse_number(&arg, &value()?)?,
+                "--token-budget" => config.token_budget = parse_number(&arg, &value()?)?,
+                "--schema-budget" => config.schema_budget = parse_number(&arg, &value()?)?,
+                "--schema-mode" => config.schema_mode = SchemaMode::parse(&value()?)?,
+                "--sql-retries" => config.sql_retries = parse_number(&arg, &value()?)?,
+                "--history-file" => config.history_file = Some(PathBuf::from(value()?)),
+                "--history-size" => config.history_size = parse_number(&arg, &value()?)?,
+                "--config" => config_file = Some(PathBuf::from(value()?)),
+                "--keymap" => keymap = Some(value()?),
+                _ => return Err(format!("unknown argument '{}'", arg).into()),
+            }
+        }
+
+        let settings = match config_file.or_else(|| config_dir().map(|dir| dir.join("data_bot2").join("config.json"))) {
+            Some(path) => read_settings(&path)?,
+            None => Value::Null,
+        };
+        config.keymap = KeyMap::preset(keymap.as_deref().or(settings["keymap"].as_str()).unwrap_or("emacs"))?;
+        if let Some(bindings) = settings["bindings"].as_object() {
+            for (key, action) in bindings {
+                let action = action.as_str().ok_or(format!("the action bound to {} must be a string", key))?;
+                config.keymap.bind(key, action)?;
+            }
+        }
+
+        if config.allowed_dirs.is_empty() {
+            config.allowed_dirs.push(env::current_dir()?);
+        }
+        // Relative directories are taken from where Data was started, `cd` does not move them.
+        // Directories that do not exist allow nothing
+        config.allowed_dirs = config.allowed_dirs.iter()
+            .filter_map(|dir| dir.canonicalize().ok())
+            .collect();
+        Ok(config)
+    }
+}
+
+/// The config file, a file that does not exist is the same as an empty one.
+fn read_settings(path: &Path) -> Result<Value, Box<dyn Error>> {
+    match fs::read_to_string(path) {
+        Ok(text) => serde_json::from_str(&text).map_err(|e| format!("could not read {}: {}", path.display(), e).into()),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Value::Null),
+        Err(e) => Err(format!("could not read {}: {}", path.display(), e).into()),
+    }
+}
+
+/// The directory applications keep their settings in: `$XDG_CONFIG_HOME` or `~/.config` on Linux,
+/// `~/Library/Application Support` on macOS and `%APPDATA%` on Windows.
+pub fn config_dir() -> Option<PathBuf> {
+    if cfg!(windows) {
+        return env::var_os("APPDATA").map(PathBuf::from);
+    }
+    if let Some(dir) = env::var_os("XDG_CONFIG_HOME").filter(|dir| !dir.is_empty()) {
+        return Some(PathBuf::from(dir));
+    }
+    let home = PathBuf::from(env::var_os("HOME")?);
+    match cfg!(target_os = "macos") {
+        true => Some(home.join("Library").join("Application Support")),
+        false => Some(home.join(".config")),
+    }
+}
+
+/// The directory applications keep their data in: `$XDG_DATA_HOME` or `~/.local/share` on Linux,
+/// `~/Library/Application Support` on macOS and `%APPDATA%` on Windows.
+pub fn data_dir() -> Option<PathBuf> {
+    if cfg!(windows) {
+        return env::var_os("APPDATA").map(PathBuf::from);
+    }
+    if let Some(dir) = env::var_os("XDG_DATA_HOME").filter(|dir| !dir.is_empty()) {
+        return Some(PathBuf::from(dir));
+    }
+    let home = PathBuf::from(env::var_os("HOME")?);
+    match cfg!(target_os = "macos") {
+        true => Some(home.join("Library").join("Application Support")),
+        false => Some(home.join(".local").join("share")),
+    }
+}
+
+fn parse_number(name: &str, value: &str) -> Result<usize, Box<dyn Error>> {
+    match value.parse::<usize>() {
+        Ok(number) => Ok(number),
+        Err(_) => Err(format!("{} expects a number, got '{}'", name, value).into()),
+    }
+}
@@ -0,0 +1,169 @@
+use std::error::Error;
+use std::path::Path;
+
+use sqlx::{Pool, Sqlite};
+
+/// An open database, known to the user and the assistant by its alias.
+#[derive(Debug)]
+pub struct Connection {
+    pub alias: String,
+    // What the user typed to connect, shown in the prompt
+    pub path: String,
+    pub pool: Pool<Sqlite>,
+    // Files attached to every connection of the pool, as `(schema, path)`
+    pub attached: Vec<(String, String)>,
+}
+
+/// Every database the REPL is connected to. One of them is active: it is shown in the prompt
+/// and used by `sql` and by `db_query` calls that do not name a database.
+#[derive(Default)]
+pub struct Connections {
+    connections: Vec<Connection>,
+    active: Option<String>,
+}
+
+impl Connection {
+    /// Whether the database only lives in memory, so it can be written to without risk to a file.
+    pub fn in_memory(&self) -> bool {
+        self.path.contains(":memory:") || self.path.contains("mode=memory")
+    }
+}
+
+impl Connections {
+    /// Registers a new connection and makes it the active one.
+    pub fn add(&mut self, alias: &str, path: &str, pool: Pool<Sqlite>) -> Result<&Connection, Box<dyn Error>> {
+        if self.get(alias).is_some() {
+            return Err(format!("a database is already connected as {}, disconnect it first", alias).into());
+        }
+        self.connections.push(Connection {
+            alias: alias.to_string(),
+            path: path.to_string(),
+            pool,
+            attached: Vec::new(),
+        });
+        self.active = Some(alias.to_string());
+        Ok(&self.connections[self.connections.len() - 1])
+    }
+
+    pub fn get(&self, alias: &str) -> Option<&Connection> {
+        self.connections.iter().find(|connection| connection.alias == alias)
+    }
+
+    pub fn active(&self) -> Option<&Connection> {
+        self.active.as_deref().and_then(|alias| self.get(alias))
+    }
+
+    pub fn active_mut(&mut self) -> Option<&mut Connection> {
+        let alias = self.active.clone()?;
+        self.connections.iter_mut().find(|connection| connection.alias == alias)
+    }
+
+    /// Makes `alias` the active connection.
+    pub fn activate(&mut self, alias: &str) -> Result<&Connection, Box<dyn Error>> {
+        if self.get(alias).is_none() {
+            return Err(self.unknown(alias).into());
+        }
+        self.active = Some(alias.to_string());
+        Ok(self.get(alias).unwrap())
+    }
+
+    /// Unregisters a connection, the most recent remaining one becomes active if it was.
+    pub fn remove(&mut self, alias: &str) -> Result<Connection, Box<dyn Error>> {
+        let index = match self.connections.iter().position(|connection| connection.alias == alias) {
+            Some(index) => index,
+            None => return Err(self.unknown(alias).into()),
+        };
+        let connection = self.connections.remove(index);
+        if self.active.as_deref() == Some(alias) {
+            self.active = self.connections.last().map(|connection| connection.alias.clone());
+        }
+        Ok(connection)
+    }
+
+    /// The connection a tool call should use, the active one unless it names another.
+    pub fn resolve(&self, alias: Option<&str>) -> Result<&Connection, String> {
+        match alias {
+            Some(alias) => self.get(alias).ok_or_else(|| self.unknown(alias)),
+            None => self.active().ok_or_else(|| "no database connected".to_string()),
+        }
+    }
+
+    /// `alias`, or `alias_2`, `alias_3` and so on when it is taken.
+    pub fn unique_alias(&self, alias: &str) -> String {
+        let mut unique = alias.to_string();
+        let mut number = 1;
+        while self.get(&unique).is_some() {
+            number += 1;
+            unique = format!("{}_{}", alias, number);
+        }
+        unique
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Connection> {
+        self.connections.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.connections.is_empty()
+    }
+
+    fn unknown(&self, alias: &str) -> String {
+        let aliases = self.connections.iter()
+            .map(|connection| connection.alias.as_str())
+            .collect::<Vec<&str>>();
+        format!("no database connected as {}, connected databases are: {}", alias, aliases.join(", "))
+    }
+}
+
+/// The alias used when `connect` is not given one: the file name without its extension.
+pub fn default_alias(path: &str) -> String {
+    let path = path.trim_start_matches("sqlite://").trim_start_matches("sqlite:");
+    let path = path.split('?').next().unwrap_or(path);
+    let stem = Path::new(path).file_stem()
+        .map(|stem| stem.to_string_lossy().trim_matches(':').to_string())
+        .unwrap_or_default();
+    match stem.is_empty() {
+        true => "db".to_string(),
+        false => stem,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql_ops::DataBase;
+
+    async fn memory() -> Pool<Sqlite> {
+        DataBase::create_connection("sqlite::memory:", false).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn connections_are_found_by_alias() {
+        let mut connections = Connections::default();
+        connections.add("sales", "sales.db", memory().await).unwrap();
+        connections.add("hr", "people.db", memory().await).unwrap();
+        assert!(connections.add("hr", "other.db", memory().await).is_err());
+        assert_eq!(connections.unique_alias("hr"), "hr_2");
+        assert_eq!(connections.unique_alias("archive"), "archive");
+
+        assert_eq!(connections.active().unwrap().alias, "hr");
+        assert_eq!(connections.resolve(None).unwrap().path, "people.db");
+        assert_eq!(connections.resolve(Some("sales")).unwrap().path, "sales.db");
+        assert!(!connections.active().unwrap().in_memory());
+        assert!(connections.resolve(Some("missing")).unwrap_err().contains("sales, hr"));
+
+        connections.activate("sales").unwrap();
+        assert_eq!(connections.resolve(None).unwrap().alias, "sales");
+        connections.remove("sales").unwrap();
+        assert_eq!(connections.active().unwrap().alias, "hr");
+        connections.remove("hr").unwrap();
+        assert!(connections.active().is_none());
+    }
+
+    #[test]
+    fn aliases_default_to_the_file_name() {
+        assert_eq!(default_alias("data/sales.db"), "sales");
+        assert_eq!(default_alias("sqlite://people.sqlite?mode=ro"), "people");
+        assert_eq!(default_alias("sqlite::memory:"), "memory");
+    }
+}
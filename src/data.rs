@@ -0,0 +1,780 @@
+use std::error::Error;
+use std::path::{Path, PathBuf};
+
+use crossterm::style::Color;
+use crate::backend::{Backend, RunState, ToolCall, ToolOutput};
+use crate::config::SchemaMode;
+use crate::connections::{self, Connections};
+use crate::ledit;
+use crate::schema;
+use crate::sql_ops::{DataBase, ResultSet, StatementKind};
+use crate::style;
+use crate::tool_output::{self, Budget};
+
+/// What tool calls run against, set up by the REPL for each `data` question.
+pub struct ToolContext<'a> {
+    // The assistant may connect to more databases with `connect_to_database`
+    pub connections: &'a mut Connections,
+    // Paths given to `connect_to_database` are relative to this
+    pub working_dir: &'a Path,
+    // Directories `connect_to_database` may open files in, as canonical paths
+    pub allowed_dirs: &'a [PathBuf],
+    // Whether a database connected by the assistant is described in full or by its table index
+    pub schema_mode: SchemaMode,
+    // The result of the last query that ran, kept for `export`
+    pub last_result: Option<ResultSet>,
+    // Query results are cut down to this before being sent back
+    pub budget: &'a Budget,
+    // Statements that modify the database are rejected unless this is set,
+    // and even then each one has to be confirmed by the user
+    pub allow_writes: bool,
+    // Show every generated query to the user, who can run, edit or reject it
+    pub approve_queries: bool,
+    // How many failed queries the assistant may correct and rerun per question
+    pub sql_retries: usize,
+    // Failed queries so far in the current question
+    pub failed_queries: usize,
+}
+
+/// What the user decided to do with a generated query.
+enum Review {
+    Run(String),
+    Reject(String),
+}
+
+/// Asks the assistant a question about the connected database and prints its reply.
+/// Tool calls made by the assistant are executed against `ctx` and answered until the run settles.
+pub async fn ask<B: Backend>(backend: &mut B, ctx: &mut ToolContext<'_>, question: &str) -> Result<(), Box<dyn Error>> {
+    ctx.failed_queries = 0;
+    backend.send_user_message(question).await?;
+    backend.run().await?;
+
+    loop {
+        match backend.poll().await? {
+            RunState::Queued | RunState::InProgress => {
+                style::print(Color::Green, Color::Reset, "=")?;
+            },
+            RunState::Completed => {
+                style::println(Color::Green, Color::Reset, "+")?;
+                let mut write = |text: &str| style::print(Color::Magenta, Color::Reset, text);
+                match backend.stream_answer(&mut write).await {
+                    Ok(()) => println!(),
+                    Err(e) => style::println(Color::Red, Color::Reset, format!("\nError: {}", e).as_str())?,
+                }
+                return Ok(());
+            },
+            RunState::RequiresAction(tool_calls) => {
+                style::print(Color::Yellow, Color::Reset, "=")?;
+                let mut outputs = Vec::new();
+                for tool_call in tool_calls {
+                    outputs.push(call_tool(ctx, tool_call).await?);
+                }
+                backend.submit_tool_outputs(outputs).await?;
+            },
+            RunState::Failed(reason) => {
+                style::print(Color::Red, Color::Reset, "Error: ")?;
+                style::println(Color::Reset, Color::Reset, format!("run failed: {}", reason).as_str())?;
+                return Ok(());
+            },
+            RunState::Cancelled => {
+                style::print(Color::Red, Color::Reset, "Error: ")?;
+                style::println(Color::Reset, Color::Reset, "run cancelled")?;
+                return Ok(());
+            },
+            RunState::Expired => {
+                style::print(Color::Red, Color::Reset, "Error: ")?;
+                style::println(Color::Reset, Color::Reset, "run expired")?;
+                return Ok(());
+            },
+        }
+    }
+}
+
+/// Executes a single tool call and returns the output to send back to the assistant.
+async fn call_tool(ctx: &mut ToolContext<'_>, tool_call: ToolCall) -> Result<ToolOutput, Box<dyn Error>> {
+    style::print(Color::Magenta, Color::Reset, "=")?;
+    let output = match tool_call.name.as_str() {
+        "db_query" => {
+            style::print(Color::Blue, Color::Reset, "=")?;
+            let arguments: serde_json::Value = serde_json::from_str(&tool_call.arguments)?;
+            match arguments["query"].as_str() {
+                Some(query) => db_query(ctx, query, arguments["database"].as_str()).await?,
+                None => {
+                    style::println(Color::Red, Color::Reset, "Error: query not found")?;
+                    "Error: query not found".to_string()
+                }
+            }
+        },
+        "connect_to_database" => {
+            let arguments: serde_json::Value = serde_json::from_str(&tool_call.arguments)?;
+            match arguments["database"].as_str() {
+                Some(database) => connect_to_database(ctx, database, arguments["alias"].as_str()).await?,
+                None => {
+                    style::println(Color::Red, Color::Reset, "Error: database not found")?;
+                    "Error: database not found".to_string()
+                }
+            }
+        },
+        "list_tables" | "describe_table" | "search_columns" => {
+            style::print(Color::Blue, Color::Reset, "=")?;
+            let arguments: serde_json::Value = serde_json::from_str(&tool_call.arguments)?;
+            schema_tool(ctx, &tool_call.name, &arguments).await?
+        },
+        "hello_world" => {
+            println!("hello world");
+            "function executed correctly".to_string()
+        },
+        _ => {
+            style::println(Color::Red, Color::Reset, "Error: assistant function not found")?;
+            format!("Error: function {} not found", tool_call.name)
+        },
+    };
+
+    Ok(ToolOutput{
+        tool_call_id: tool_call.id,
+        output,
+    })
+}
+
+/// Runs SQL generated by the assistant, showing the results to the user and returning them for the model.
+async fn db_query(ctx: &mut ToolContext<'_>, query: &str, database: Option<&str>) -> Result<String, Box<dyn Error>> {
+    let connection = match ctx.connections.resolve(database) {
+        Ok(connection) => connection,
+        Err(e) => {
+            style::print(Color::Red, Color::Reset, "\nError: ")?;
+            style::println(Color::Reset, Color::Reset, &e)?;
+            return Ok(format!("Error: {}", e));
+        },
+    };
+    // The assistant was told no retries are left, anything it runs after that is refused
+    if ctx.failed_queries > ctx.sql_retries {
+        style::print(Color::Red, Color::Reset, "\nrefused: ")?;
+        style::println(Color::DarkMagenta, Color::Reset, query)?;
+        style::println(Color::DarkGrey, Color::Reset, "no retries left for this question")?;
+        return Ok(format!("Error: query not run, {} queries failed and no retries are left, explain the error to the user", ctx.failed_queries));
+    }
+    let generated = query;
+    let query = if ctx.approve_queries {
+        match review_query(query)? {
+            Review::Run(query) => query,
+            Review::Reject(reason) => return Ok(format!("Error: the user rejected the query, reason: {}", reason)),
+        }
+    } else {
+        query.to_string()
+    };
+    let query = query.as_str();
+
+    let query_type = match StatementKind::of(query) {
+        StatementKind::ReadOnly => "fetch",
+        StatementKind::Mutating if !ctx.allow_writes => {
+            style::print(Color::Red, Color::Reset, "\nrejected: ")?;
+            style::println(Color::DarkMagenta, Color::Reset, query)?;
+            style::println(Color::DarkGrey, Color::Reset, "the database is read-only, use allow-writes to let Data modify it")?;
+            return Ok("Error: statement rejected, the database is read-only and only SELECT, WITH, EXPLAIN and read-only PRAGMA statements are allowed".to_string());
+        },
+        StatementKind::Mutating => {
+            // A query the user just approved in the editor needs no second confirmation
+            if !ctx.approve_queries && !confirm_write(query)? {
+                return Ok("Error: statement rejected by the user".to_string());
+            }
+            "execute"
+        },
+    };
+
+    let query_response = match DataBase::query(
+        connection.pool.clone(),
+        query.to_string(),
+        query_type.to_string())
+        .await{
+            Ok(query_response) => query_response,
+            Err(e) => return query_failed(ctx, query, e.as_ref()),
+        };
+    let result = ResultSet::from_rows(&query_response);
+    DataBase::pretty_print_data(&result)?;
+    let output = tool_output::result_set(&result, ctx.budget);
+    ctx.last_result = Some(result);
+    if query != generated {
+        return Ok(format!("the user edited the query before running it, the query that ran was: {}\n{}", query, output));
+    }
+    Ok(output)
+}
+
+/// Runs SQL typed by the user with the `sql` command, without going through the assistant.
+/// Statements that modify an in-memory database are allowed even when writes are not,
+/// unless a file is or would be attached to it.
+/// Returns `None` when the statement was rejected or failed, which has already been reported to the user.
+pub async fn run_sql(ctx: &ToolContext<'_>, query: &str) -> Result<Option<ResultSet>, Box<dyn Error>> {
+    let connection = match ctx.connections.active() {
+        Some(connection) => connection,
+        None => {
+            style::print(Color::Red, Color::Reset, "Error: ")?;
+            style::println(Color::Reset, Color::Reset, "no database connected")?;
+            return Ok(None);
+        },
+    };
+    let query_type = match StatementKind::of(query) {
+        StatementKind::ReadOnly => "fetch",
+        StatementKind::Mutating if !ctx.allow_writes && !connection.in_memory() => {
+            style::print(Color::Red, Color::Reset, "Error: ")?;
+            style::println(Color::Reset, Color::Reset, "the database is read-only, use allow-writes to modify it")?;
+            return Ok(None);
+        },
+        // Scratch tables in memory put no file at risk, but writes go through a connection
+        // that can also write to attached files
+        StatementKind::Mutating if !ctx.allow_writes && (!connection.attached.is_empty() || StatementKind::attaches(query)) => {
+            style::print(Color::Red, Color::Reset, "Error: ")?;
+            style::println(Color::Reset, Color::Reset, "files are attached to this database, use allow-writes to modify it")?;
+            return Ok(None);
+        },
+        StatementKind::Mutating => "execute",
+    };
+
+    match DataBase::query(connection.pool.clone(), query.to_string(), query_type.to_string()).await {
+        Ok(query_response) => {
+            let result = ResultSet::from_rows(&query_response);
+            DataBase::pretty_print_data(&result)?;
+            Ok(Some(result))
+        },
+        Err(e) => {
+            style::print(Color::Red, Color::Reset, "Error: query failed: ")?;
+            style::println(Color::Reset, Color::Reset, &e.to_string())?;
+            Ok(None)
+        },
+    }
+}
+
+/// Opens a database for the assistant, after checking the file is inside an allowed directory
+/// and the user agrees. The output is the schema of the new database, which becomes the active one.
+async fn connect_to_database(ctx: &mut ToolContext<'_>, database: &str, alias: Option<&str>) -> Result<String, Box<dyn Error>> {
+    let path = match allowed_path(ctx, database) {
+        Ok(path) => path,
+        Err(e) => {
+            style::print(Color::Red, Color::Reset, "\nrefused: ")?;
+            style::println(Color::Reset, Color::Reset, &e)?;
+            return Ok(format!("Error: {}", e));
+        },
+    };
+    let path = path.display().to_string();
+
+    style::print(Color::Yellow, Color::Reset, "\nData wants to connect to: ")?;
+    style::println(Color::Blue, Color::Reset, &path)?;
+    let answer = ledit::prompt("open this database? [y/N] ");
+    if !matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+        return Ok("Error: the user did not allow connecting to this database".to_string());
+    }
+
+    open_database(ctx, &path, alias).await
+}
+
+/// Connects to a database the user agreed to open and describes it. The connection is only
+/// registered once its schema could be read, failures are reported back to the assistant.
+async fn open_database(ctx: &mut ToolContext<'_>, path: &str, alias: Option<&str>) -> Result<String, Box<dyn Error>> {
+    let pool = match DataBase::create_connection(path, true).await {
+        Ok(pool) => pool,
+        Err(e) => {
+            style::print(Color::Red, Color::Reset, "Error: ")?;
+            style::println(Color::Reset, Color::Reset, format!("could not read database: {}", e).as_str())?;
+            return Ok(format!("Error: could not read database: {}", e));
+        },
+    };
+    let db_details = match schema::introduce(&pool, ctx.schema_mode, ctx.budget).await {
+        Ok(db_details) => db_details,
+        Err(e) => {
+            pool.close().await;
+            style::print(Color::Red, Color::Reset, "Error: ")?;
+            style::println(Color::Reset, Color::Reset, format!("could not read the schema: {}", e).as_str())?;
+            return Ok(format!("Error: could not read the schema: {}", e));
+        },
+    };
+    let alias = ctx.connections.unique_alias(alias.map(str::to_string).unwrap_or_else(|| connections::default_alias(path)).as_str());
+    if let Err(e) = ctx.connections.add(&alias, path, pool) {
+        style::print(Color::Red, Color::Reset, "Error: ")?;
+        style::println(Color::Reset, Color::Reset, &e.to_string())?;
+        return Ok(format!("Error: {}", e));
+    }
+    style::print(Color::Green, Color::Reset, "connected")?;
+    style::print(Color::Reset, Color::Reset, " to ")?;
+    style::print(Color::Blue, Color::Reset, path)?;
+    style::print(Color::Reset, Color::Reset, " as ")?;
+    style::println(Color::Blue, Color::Reset, &alias)?;
+    Ok(format!("connected to Database {}, it is now the active database >>> database info: \n {}", alias, db_details))
+}
+
+/// Answers the schema lookups the assistant makes instead of being sent the whole schema of a large database.
+/// A lookup that fails is reported back, so the assistant can carry on without it.
+async fn schema_tool(ctx: &ToolContext<'_>, name: &str, arguments: &serde_json::Value) -> Result<String, Box<dyn Error>> {
+    match lookup_schema(ctx, name, arguments).await {
+        Ok(output) => Ok(output),
+        Err(e) => {
+            style::print(Color::Red, Color::Reset, "\nError: ")?;
+            style::println(Color::Reset, Color::Reset, format!("{} failed: {}", name, e).as_str())?;
+            Ok(format!("Error: {} failed: {}", name, e))
+        },
+    }
+}
+
+async fn lookup_schema(ctx: &ToolContext<'_>, name: &str, arguments: &serde_json::Value) -> Result<String, Box<dyn Error>> {
+    let connection = match ctx.connections.resolve(arguments["database"].as_str()) {
+        Ok(connection) => connection,
+        Err(e) => return Ok(format!("Error: {}", e)),
+    };
+    let entries = schema::list_tables(&connection.pool).await?;
+    match name {
+        "list_tables" => {
+            let pattern = arguments["pattern"].as_str().unwrap_or("").to_lowercase();
+            let entries = entries.into_iter()
+                .filter(|entry| entry.qualified_name().to_lowercase().contains(&pattern))
+                .collect::<Vec<schema::TableEntry>>();
+            Ok(schema::table_index(&entries, ctx.budget, ctx.budget.max_tokens))
+        },
+        "describe_table" => {
+            let table = arguments["table"].as_str().unwrap_or("");
+            match schema::describe_table(&connection.pool, table, ctx.budget, ctx.budget.max_tokens).await? {
+                Some(description) => Ok(description),
+                None => Ok(format!("Error: table {} not found, call list_tables to see the tables", table)),
+            }
+        },
+        _ => {
+            let pattern = arguments["pattern"].as_str().unwrap_or("");
+            let matches = schema::search_columns(&entries, pattern);
+            let shown = matches.len().min(ctx.budget.max_rows);
+            let mut output = serde_json::json!({"columns": matches[..shown]});
+            if shown < matches.len() {
+                output["note"] = serde_json::json!(format!("{} more columns, use a longer pattern", matches.len() - shown));
+            }
+            Ok(output.to_string())
+        },
+    }
+}
+
+/// Resolves a path requested by the assistant against the working directory,
+/// refusing files that do not exist or lie outside every allowed directory.
+fn allowed_path(ctx: &ToolContext<'_>, database: &str) -> Result<PathBuf, String> {
+    let database = database.trim_start_matches("sqlite://");
+    let path = match ctx.working_dir.join(database).canonicalize() {
+        Ok(path) => path,
+        Err(_) => return Err(format!("{} does not exist", database)),
+    };
+    // Canonical paths, so `..` and symlinks can not lead outside an allowed directory
+    let allowed = ctx.allowed_dirs.iter().any(|dir| path.starts_with(dir));
+    if !path.is_file() || !allowed {
+        return Err(format!("{} is not in a directory the assistant is allowed to open", database));
+    }
+    Ok(path)
+}
+
+/// Reports a failed query to the user and builds the error output the assistant uses to correct it.
+fn query_failed(ctx: &mut ToolContext<'_>, query: &str, error: &(dyn Error + 'static)) -> Result<String, Box<dyn Error>> {
+    ctx.failed_queries += 1;
+    let retries_left = ctx.sql_retries.saturating_sub(ctx.failed_queries);
+    // Database errors carry sqlite's own message, which is what the model needs to fix the query
+    let (code, message) = match error.downcast_ref::<sqlx::Error>().and_then(|e| e.as_database_error()) {
+        Some(e) => (e.code().map(|code| code.to_string()), e.message().to_string()),
+        None => (None, error.to_string()),
+    };
+
+    style::print(Color::Red, Color::Reset, "Error: query failed: ")?;
+    style::println(Color::Reset, Color::Reset, &message)?;
+    if ctx.failed_queries <= ctx.sql_retries {
+        style::println(Color::DarkGrey, Color::Reset, "Data may correct the query and try again")?;
+    }
+    Ok(tool_output::query_error(query, code.as_deref(), &message, retries_left))
+}
+
+/// Shows a generated query in the line editor, where the user can run it as is,
+/// edit it in place, or clear the line to reject it and give the assistant a reason.
+fn review_query(query: &str) -> Result<Review, Box<dyn Error>> {
+    style::print(Color::Yellow, Color::Reset, "\nData wants to run this query")?;
+    if StatementKind::of(query) == StatementKind::Mutating {
+        style::print(Color::Red, Color::Reset, ", it modifies the database")?;
+    }
+    style::println(Color::DarkGrey, Color::Reset, " (enter to run, edit it first, or clear the line to reject)")?;
+    let reviewed = ledit::prompt_with("sql> ", query, Some(style::highlight_sql));
+    let reviewed = reviewed.trim();
+    if !reviewed.is_empty() {
+        return Ok(Review::Run(reviewed.to_string()));
+    }
+    let reason = ledit::prompt("reason for rejecting: ");
+    let reason = match reason.trim() {
+        "" => "no reason given",
+        reason => reason,
+    };
+    Ok(Review::Reject(reason.to_string()))
+}
+
+/// Asks the user whether a statement that modifies the database may run.
+fn confirm_write(query: &str) -> Result<bool, Box<dyn Error>> {
+    style::print(Color::Yellow, Color::Reset, "\nData wants to modify the database: ")?;
+    style::println(Color::DarkMagenta, Color::Reset, query)?;
+    let answer = ledit::prompt("run this statement? [y/N] ");
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::MockBackend;
+    use crate::fixtures;
+    use sqlx::{Pool, Sqlite};
+
+    fn budget() -> Budget {
+        Budget::new("gpt-3.5-turbo-16k", 100, 2000, 4000).unwrap()
+    }
+
+    // A directory of its own, so no fixture is ever allowed and no test reaches a confirmation prompt
+    fn allowed_dirs() -> Vec<PathBuf> {
+        vec![std::env::temp_dir().join(format!("data_bot2_allowed_{}", std::process::id()))]
+    }
+
+    fn connections(db: &Pool<Sqlite>) -> Connections {
+        let mut connections = Connections::default();
+        connections.add("sample", "sample.db", db.clone()).unwrap();
+        connections
+    }
+
+    fn context<'a>(connections: &'a mut Connections, budget: &'a Budget, allowed_dirs: &'a [PathBuf]) -> ToolContext<'a> {
+        ToolContext {
+            connections,
+            working_dir: Path::new(env!("CARGO_MANIFEST_DIR")),
+            allowed_dirs,
+            budget,
+            allow_writes: false,
+            approve_queries: false,
+            sql_retries: 2,
+            failed_queries: 0,
+            schema_mode: SchemaMode::Auto,
+            last_result: None,
+        }
+    }
+
+    fn tool_call(id: &str, name: &str, arguments: &str) -> ToolCall {
+        ToolCall{
+            id: id.to_string(),
+            name: name.to_string(),
+            arguments: arguments.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn db_query_results_are_submitted_to_the_assistant() {
+        let db = fixtures::database("db_query_results").await;
+        let mut backend = MockBackend::from_file(&fixtures::path("db_query_script.json")).unwrap();
+
+        ask(&mut backend, &mut context(&mut connections(&db), &budget(), &allowed_dirs()), "what are the users called?").await.unwrap();
+
+        assert_eq!(backend.messages, vec!["what are the users called?"]);
+        assert_eq!(backend.runs, 1);
+        assert_eq!(backend.tool_outputs.len(), 1);
+        let output = &backend.tool_outputs[0];
+        assert_eq!(output.tool_call_id, "call_1");
+        for name in ["Ada", "Grace", "Linus"] {
+            assert!(output.output.contains(name), "{} missing from {}", name, output.output);
+        }
+    }
+
+    #[tokio::test]
+    async fn every_tool_call_gets_an_output() {
+        let db = fixtures::database("every_tool_call").await;
+        let mut backend = MockBackend::new(vec![
+            RunState::InProgress,
+            RunState::RequiresAction(vec![
+                tool_call("call_1", "db_query", "{\"query\": \"SELECT COUNT(*) AS n FROM orders\"}"),
+                tool_call("call_2", "no_such_function", "{}"),
+            ]),
+            RunState::Completed,
+        ], vec!["Database Queried".to_string()]);
+
+        ask(&mut backend, &mut context(&mut connections(&db), &budget(), &allowed_dirs()), "how many orders are there?").await.unwrap();
+
+        let ids = backend.tool_outputs.iter()
+            .map(|output| output.tool_call_id.as_str())
+            .collect::<Vec<&str>>();
+        assert_eq!(ids, vec!["call_1", "call_2"]);
+        assert!(backend.tool_outputs[0].output.contains('3'));
+        assert!(backend.tool_outputs[1].output.contains("not found"));
+    }
+
+    #[tokio::test]
+    async fn queries_can_name_a_database() {
+        let db = fixtures::database("queries_can_name").await;
+        let mut connections = connections(&db);
+        let scratch = sqlx::SqlitePool::connect("sqlite::memory:").await.unwrap();
+        sqlx::query("CREATE TABLE notes (body TEXT); INSERT INTO notes VALUES ('scratch note')")
+            .execute(&scratch).await.unwrap();
+        connections.add("scratch", "sqlite::memory:", scratch).unwrap();
+        let mut backend = MockBackend::new(vec![
+            RunState::RequiresAction(vec![
+                tool_call("call_1", "db_query", "{\"query\": \"SELECT name FROM users\", \"database\": \"sample\"}"),
+                tool_call("call_2", "db_query", "{\"query\": \"SELECT body FROM notes\"}"),
+                tool_call("call_3", "db_query", "{\"query\": \"SELECT 1\", \"database\": \"sales\"}"),
+            ]),
+            RunState::Completed,
+        ], Vec::new());
+
+        ask(&mut backend, &mut context(&mut connections, &budget(), &allowed_dirs()), "what is in each database?").await.unwrap();
+
+        assert!(backend.tool_outputs[0].output.contains("Ada"));
+        // Without a database the active one, connected last, is queried
+        assert!(backend.tool_outputs[1].output.contains("scratch note"));
+        assert!(backend.tool_outputs[2].output.contains("no database connected as sales"));
+    }
+
+    #[tokio::test]
+    async fn databases_outside_the_allowed_directories_are_refused() {
+        let db = fixtures::database("outside_allowed").await;
+        let mut backend = MockBackend::new(vec![
+            RunState::RequiresAction(vec![
+                tool_call("call_1", "connect_to_database", "{\"database\": \"tests/fixtures/sample.sql\"}"),
+                tool_call("call_2", "connect_to_database", "{\"database\": \"no_such_file.db\"}"),
+                tool_call("call_3", "connect_to_database", "{}"),
+            ]),
+            RunState::Completed,
+        ], Vec::new());
+        let mut connections = connections(&db);
+
+        ask(&mut backend, &mut context(&mut connections, &budget(), &allowed_dirs()), "open the fixtures").await.unwrap();
+
+        assert!(backend.tool_outputs[0].output.contains("not in a directory the assistant is allowed to open"));
+        assert!(backend.tool_outputs[1].output.contains("does not exist"));
+        assert!(backend.tool_outputs[2].output.contains("Error"));
+        assert_eq!(connections.iter().count(), 1);
+    }
+
+    #[tokio::test]
+    async fn opened_databases_become_active() {
+        let db = fixtures::database("opened_databases").await;
+        let budget = budget();
+        let mut connections = Connections::default();
+        let allowed_dirs = allowed_dirs();
+        let mut ctx = context(&mut connections, &budget, &allowed_dirs);
+
+        let output = open_database(&mut ctx, &fixtures::database_file("opened_databases"), Some("shop")).await.unwrap();
+        assert!(output.contains("connected to Database shop"), "{}", output);
+        assert!(output.contains("orders") && output.contains("users"), "{}", output);
+        // A file that is not a database is reported and never registered
+        let output = open_database(&mut ctx, &fixtures::path("sample.sql"), Some("broken")).await.unwrap();
+        assert!(output.starts_with("Error"), "{}", output);
+        assert_eq!(connections.active().unwrap().alias, "shop");
+        assert!(connections.get("broken").is_none());
+        db.close().await;
+    }
+
+    #[tokio::test]
+    async fn schema_is_looked_up_with_tools() {
+        let db = fixtures::database("schema_tools").await;
+        let mut backend = MockBackend::new(vec![
+            RunState::RequiresAction(vec![
+                tool_call("call_1", "list_tables", "{\"pattern\": \"user\"}"),
+                tool_call("call_2", "describe_table", "{\"table\": \"orders\"}"),
+                tool_call("call_3", "search_columns", "{\"pattern\": \"email\"}"),
+                tool_call("call_4", "describe_table", "{\"table\": \"payments\"}"),
+            ]),
+            RunState::Completed,
+        ], Vec::new());
+
+        ask(&mut backend, &mut context(&mut connections(&db), &budget(), &allowed_dirs()), "which table has emails?").await.unwrap();
+
+        let outputs = backend.tool_outputs.iter().map(|output| output.output.as_str()).collect::<Vec<&str>>();
+        assert!(outputs[0].contains("users (4 columns)") && !outputs[0].contains("orders"));
+        assert!(outputs[1].contains("REFERENCES users(id)"));
+        assert_eq!(outputs[2], "{\"columns\":[\"users.email TEXT\"]}");
+        assert!(outputs[3].contains("payments not found"));
+    }
+
+    #[tokio::test]
+    async fn failed_schema_lookups_do_not_end_the_run() {
+        let db = fixtures::database("failed_schema_lookups").await;
+        let mut connections = connections(&db);
+        let broken = DataBase::create_connection(&fixtures::path("sample.sql"), true).await.unwrap();
+        connections.add("broken", "sample.sql", broken).unwrap();
+        let mut backend = MockBackend::new(vec![
+            RunState::RequiresAction(vec![
+                tool_call("call_1", "list_tables", "{}"),
+                tool_call("call_2", "describe_table", "{\"table\": \"users\", \"database\": \"sample\"}"),
+            ]),
+            RunState::Completed,
+        ], Vec::new());
+
+        ask(&mut backend, &mut context(&mut connections, &budget(), &allowed_dirs()), "what is in there?").await.unwrap();
+
+        assert!(backend.tool_outputs[0].output.starts_with("Error: list_tables failed"), "{}", backend.tool_outputs[0].output);
+        assert!(backend.tool_outputs[1].output.contains("email"));
+    }
+
+    #[tokio::test]
+    async fn failed_queries_do_not_end_the_run() {
+        let db = fixtures::database("failed_queries").await;
+        let mut backend = MockBackend::new(vec![
+            RunState::RequiresAction(vec![
+                tool_call("call_1", "db_query", "{\"query\": \"SELECT missing FROM users\"}"),
+            ]),
+            RunState::Completed,
+        ], Vec::new());
+
+        ask(&mut backend, &mut context(&mut connections(&db), &budget(), &allowed_dirs()), "show me the missing column").await.unwrap();
+
+        assert_eq!(backend.tool_outputs.len(), 1);
+        assert_eq!(backend.tool_outputs[0].tool_call_id, "call_1");
+    }
+
+    #[tokio::test]
+    async fn query_errors_are_sent_back_for_correction() {
+        let db = fixtures::database("query_errors").await;
+        let mut backend = MockBackend::new(vec![
+            RunState::RequiresAction(vec![
+                tool_call("call_1", "db_query", "{\"query\": \"SELECT nme FROM users\"}"),
+            ]),
+            RunState::RequiresAction(vec![
+                tool_call("call_2", "db_query", "{\"query\": \"SELECT name FROM users\"}"),
+            ]),
+            RunState::Completed,
+        ], Vec::new());
+
+        ask(&mut backend, &mut context(&mut connections(&db), &budget(), &allowed_dirs()), "list the user names").await.unwrap();
+
+        let error: serde_json::Value = serde_json::from_str(&backend.tool_outputs[0].output).unwrap();
+        assert_eq!(error["error"], "no such column: nme");
+        assert_eq!(error["query"], "SELECT nme FROM users");
+        assert_eq!(error["retries_left"], 1);
+        assert!(backend.tool_outputs[1].output.contains("Grace"));
+    }
+
+    #[tokio::test]
+    async fn retries_run_out() {
+        let db = fixtures::database("retries_run_out").await;
+        let mut backend = MockBackend::new(vec![
+            RunState::RequiresAction(vec![
+                tool_call("call_1", "db_query", "{\"query\": \"SELECT nme FROM users\"}"),
+            ]),
+            RunState::Completed,
+        ], Vec::new());
+        let budget = budget();
+        let mut connections = connections(&db);
+        let allowed_dirs = allowed_dirs();
+        let mut ctx = context(&mut connections, &budget, &allowed_dirs);
+        ctx.sql_retries = 0;
+        // A previous question's failures do not count against this one
+        ctx.failed_queries = 5;
+
+        ask(&mut backend, &mut ctx, "list the user names").await.unwrap();
+
+        let error: serde_json::Value = serde_json::from_str(&backend.tool_outputs[0].output).unwrap();
+        assert_eq!(error["retries_left"], 0);
+        assert_eq!(ctx.failed_queries, 1);
+    }
+
+    #[tokio::test]
+    async fn queries_after_the_last_retry_are_not_run() {
+        let db = fixtures::database("queries_after_the_last_retry").await;
+        let mut backend = MockBackend::new(vec![
+            RunState::RequiresAction(vec![
+                tool_call("call_1", "db_query", "{\"query\": \"SELECT nme FROM users\"}"),
+            ]),
+            RunState::RequiresAction(vec![
+                tool_call("call_2", "db_query", "{\"query\": \"SELECT name FROM users\"}"),
+            ]),
+            RunState::Completed,
+        ], Vec::new());
+        let budget = budget();
+        let mut connections = connections(&db);
+        let allowed_dirs = allowed_dirs();
+        let mut ctx = context(&mut connections, &budget, &allowed_dirs);
+        ctx.sql_retries = 0;
+
+        ask(&mut backend, &mut ctx, "list the user names").await.unwrap();
+
+        let output = &backend.tool_outputs[1].output;
+        assert!(output.starts_with("Error: query not run"), "{}", output);
+        assert!(!output.contains("Ada"));
+        assert!(ctx.last_result.is_none());
+    }
+
+    #[tokio::test]
+    async fn sql_typed_by_the_user_runs_directly() {
+        let db = fixtures::database("sql_typed_by_the_user").await;
+        let budget = budget();
+        let mut connections = connections(&db);
+        let allowed_dirs = allowed_dirs();
+        let mut ctx = context(&mut connections, &budget, &allowed_dirs);
+
+        let result = run_sql(&ctx, "SELECT name FROM users ORDER BY id").await.unwrap().unwrap();
+        assert_eq!(result.rows.len(), 3);
+        assert!(run_sql(&ctx, "SELECT missing FROM users").await.unwrap().is_none());
+        assert!(run_sql(&ctx, "DELETE FROM orders").await.unwrap().is_none());
+
+        ctx.allow_writes = true;
+        run_sql(&ctx, "DELETE FROM orders WHERE id = 1").await.unwrap().unwrap();
+        let orders = sqlx::query("SELECT * FROM orders").fetch_all(&db).await.unwrap();
+        assert_eq!(orders.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn scratch_tables_in_memory_need_no_write_access() {
+        let mut connections = Connections::default();
+        let memory = DataBase::create_connection("sqlite::memory:", false).await.unwrap();
+        connections.add("memory", "sqlite::memory:", memory.clone()).unwrap();
+        let budget = budget();
+        let allowed_dirs = allowed_dirs();
+        let ctx = context(&mut connections, &budget, &allowed_dirs);
+
+        run_sql(&ctx, "CREATE TABLE notes (body TEXT)").await.unwrap().unwrap();
+        run_sql(&ctx, "INSERT INTO notes VALUES ('kept')").await.unwrap().unwrap();
+
+        let path = std::env::temp_dir().join(format!("data_bot2_saved_{}.db", std::process::id())).display().to_string();
+        let _ = std::fs::remove_file(&path);
+        DataBase::save(&memory, &path).await.unwrap();
+        let saved = DataBase::create_connection(&path, true).await.unwrap();
+        let (body,): (String,) = sqlx::query_as("SELECT body FROM notes").fetch_one(&saved).await.unwrap();
+        assert_eq!(body, "kept");
+        assert!(DataBase::save(&memory, &path).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn files_attached_in_memory_need_write_access() {
+        let db = fixtures::database("attached_in_memory").await;
+        let file = fixtures::database_file("attached_in_memory");
+        let memory = DataBase::create_connection("sqlite::memory:", false).await.unwrap();
+        let mut connections = Connections::default();
+        connections.add("memory", "sqlite::memory:", memory.clone()).unwrap();
+        let budget = budget();
+        let allowed_dirs = allowed_dirs();
+
+        let attach = format!("ATTACH DATABASE '{}' AS shop; DELETE FROM shop.orders", file);
+        assert!(run_sql(&context(&mut connections, &budget, &allowed_dirs), &attach).await.unwrap().is_none());
+
+        let attached = vec![("shop".to_string(), file)];
+        let pool = DataBase::attach(&memory, &attached).await.unwrap();
+        let connection = connections.active_mut().unwrap();
+        connection.pool = pool;
+        connection.attached = attached;
+        let mut ctx = context(&mut connections, &budget, &allowed_dirs);
+        // The attached file is read, not replaced by an empty database in memory
+        assert_eq!(run_sql(&ctx, "SELECT * FROM shop.orders").await.unwrap().unwrap().rows.len(), 3);
+        assert!(run_sql(&ctx, "DELETE FROM shop.orders").await.unwrap().is_none());
+        assert!(run_sql(&ctx, "CREATE TABLE notes (body TEXT)").await.unwrap().is_none());
+        let orders = sqlx::query("SELECT * FROM orders").fetch_all(&db).await.unwrap();
+        assert_eq!(orders.len(), 3);
+
+        ctx.allow_writes = true;
+        run_sql(&ctx, "DELETE FROM shop.orders WHERE id = 1").await.unwrap().unwrap();
+        let orders = sqlx::query("SELECT * FROM orders").fetch_all(&db).await.unwrap();
+        assert_eq!(orders.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn mutating_statements_are_rejected_when_read_only() {
+        let db = fixtures::database("mutating_statements").await;
+        let mut backend = MockBackend::new(vec![
+            RunState::RequiresAction(vec![
+                tool_call("call_1", "db_query", "{\"query\": \"SELECT 1; DROP TABLE orders\"}"),
+            ]),
+            RunState::Completed,
+        ], Vec::new());
+
+        ask(&mut backend, &mut context(&mut connections(&db), &budget(), &allowed_dirs()), "drop the orders table").await.unwrap();
+
+        assert!(backend.tool_outputs[0].output.contains("rejected"));
+        let orders = sqlx::query("SELECT * FROM orders").fetch_all(&db).await.unwrap();
+        assert_eq!(orders.len(), 3);
+    }
+}
@@ -0,0 +1,202 @@
+use std::error::Error;
+
+use crossterm::style::Color;
+use sqlx::{Pool, Sqlite};
+
+use crate::schema::{self, ForeignKey};
+use crate::style;
+
+const GAP: &str = "   ";
+
+/// A table as drawn in the diagram, with only the columns that take part in keys.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entity {
+    pub name: String,
+    pub lines: Vec<String>,
+}
+
+/// A foreign key from `table` to the table named in `key`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Relationship {
+    pub table: String,
+    pub key: ForeignKey,
+}
+
+/// Reads the tables and foreign keys of a database. With a `focus` table only that table
+/// and the tables it is directly related to are kept, so large databases stay readable.
+pub async fn load(db: &Pool<Sqlite>, focus: Option<&str>) -> Result<(Vec<Entity>, Vec<Relationship>), Box<dyn Error>> {
+    let entries = schema::list_tables(db).await?
+        .into_iter()
+        .filter(|entry| entry.kind != "view")
+        .collect::<Vec<schema::TableEntry>>();
+
+    let mut entities = Vec::new();
+    let mut relationships = Vec::new();
+    for entry in &entries {
+        let keys = schema::foreign_keys(db, &entry.schema, &entry.name).await?;
+        let mut lines = Vec::new();
+        for (column, _) in &entry.columns {
+            let primary = entry.primary_key.contains(column);
+            let foreign = keys.iter().any(|key| key.columns.contains(column));
+            match (primary, foreign) {
+                (true, true) => lines.push(format!("{} PK FK", column)),
+                (true, false) => lines.push(format!("{} PK", column)),
+                (false, true) => lines.push(format!("{} FK", column)),
+                (false, false) => {},
+            }
+        }
+        if lines.len() < entry.columns.len() {
+            lines.push(format!("+ {} more", entry.columns.len() - lines.len()));
+        }
+        entities.push(Entity { name: entry.qualified_name(), lines });
+
+        for mut key in keys {
+            // Keys that leave out the referenced columns point at the primary key
+            if key.references.is_empty() {
+                if let Some(target) = schema::find_table(&entries, &key.target()) {
+                    key.references = target.primary_key.clone();
+                }
+            }
+            relationships.push(Relationship { table: entry.qualified_name(), key });
+        }
+    }
+
+    if let Some(focus) = focus {
+        // `main.orders` is drawn as `orders`
+        let focus = schema::find_table(&entries, focus).map(|entry| entry.qualified_name()).unwrap_or(focus.to_string());
+        let focus = focus.as_str();
+        let related = |name: &str| name == focus || relationships.iter().any(|relationship|
+            (relationship.table == focus && relationship.key.target() == name) || (relationship.key.target() == focus && relationship.table == name));
+        entities.retain(|entity| related(&entity.name));
+        relationships.retain(|relationship| relationship.table == focus || relationship.key.target() == focus);
+    }
+    Ok((entities, relationships))
+}
+
+/// Prints the diagram: boxes for the tables, as many side by side as fit in `max_width`,
+/// followed by one line per foreign key.
+pub fn print(entities: &[Entity], relationships: &[Relationship], max_width: usize) -> Result<(), Box<dyn Error>> {
+    for line in diagram(entities, max_width) {
+        style::println(Color::Reset, Color::Reset, &line)?;
+    }
+    if !relationships.is_empty() {
+        style::print(Color::Reset, Color::Reset, "\n")?;
+    }
+    for relationship in relationships {
+        style::print(Color::Blue, Color::Reset, &format!("{}({})", relationship.table, relationship.key.columns.join(", ")))?;
+        style::print(Color::Cyan, Color::Reset, " >---> ")?;
+        style::println(Color::Blue, Color::Reset, &format!("{}({})", relationship.key.target(), relationship.key.references.join(", ")))?;
+    }
+    Ok(())
+}
+
+/// Lays out a box per entity in rows no wider than `max_width`.
+pub fn diagram(entities: &[Entity], max_width: usize) -> Vec<String> {
+    let boxes = entities.iter().map(draw_box).collect::<Vec<Vec<String>>>();
+    let mut lines = Vec::new();
+    let mut row: Vec<&Vec<String>> = Vec::new();
+    let mut row_width = 0;
+    for drawn in &boxes {
+        let width = drawn[0].chars().count();
+        if !row.is_empty() && row_width + GAP.len() + width > max_width {
+            lines.extend(join_row(&row));
+            lines.push(String::new());
+            row.clear();
+        }
+        row_width = match row.is_empty() {
+            true => width,
+            false => row_width + GAP.len() + width,
+        };
+        row.push(drawn);
+    }
+    if !row.is_empty() {
+        lines.extend(join_row(&row));
+    }
+    lines
+}
+
+fn draw_box(entity: &Entity) -> Vec<String> {
+    let width = entity.lines.iter()
+        .chain(std::iter::once(&entity.name))
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0);
+    let border = format!("+{}+", "-".repeat(width + 2));
+    let mut drawn = vec![border.clone(), format!("| {:<width$} |", entity.name, width = width), border.clone()];
+    if !entity.lines.is_empty() {
+        drawn.extend(entity.lines.iter().map(|line| format!("| {:<width$} |", line, width = width)));
+        drawn.push(border);
+    }
+    drawn
+}
+
+/// Puts boxes of different heights next to each other.
+fn join_row(row: &[&Vec<String>]) -> Vec<String> {
+    let height = row.iter().map(|drawn| drawn.len()).max().unwrap_or(0);
+    (0..height)
+        .map(|index| row.iter()
+            .map(|drawn| match drawn.get(index) {
+                Some(line) => line.clone(),
+                None => " ".repeat(drawn[0].chars().count()),
+            })
+            .collect::<Vec<String>>()
+            .join(GAP)
+            .trim_end()
+            .to_string())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+    use crate::sql_ops::DataBase;
+
+    #[tokio::test]
+    async fn relationships_come_from_foreign_keys() {
+        let db = fixtures::database("er_relationships").await;
+        let (entities, relationships) = load(&db, Some("users")).await.unwrap();
+
+        let names = entities.iter().map(|entity| entity.name.as_str()).collect::<Vec<&str>>();
+        assert_eq!(names, vec!["users", "orders"]);
+        assert_eq!(entities[1].lines, vec!["id PK", "user_id FK", "+ 3 more"]);
+        assert_eq!(relationships[0].table, "orders");
+        assert_eq!(relationships[0].key.references, vec!["id"]);
+    }
+
+    #[tokio::test]
+    async fn attached_relationships_are_qualified() {
+        let db = fixtures::database("er_attached").await;
+        fixtures::database("er_attached_shop").await;
+        let both = DataBase::attach(&db, &[("shop".to_string(), fixtures::database_file("er_attached_shop"))]).await.unwrap();
+
+        // Both schemas have a users table, only the one in shop is related to shop.orders
+        let (entities, relationships) = load(&both, Some("shop.orders")).await.unwrap();
+        let names = entities.iter().map(|entity| entity.name.as_str()).collect::<Vec<&str>>();
+        assert_eq!(names, vec!["shop.users", "shop.orders"]);
+        assert_eq!(relationships[0].table, "shop.orders");
+        assert_eq!(relationships[0].key.target(), "shop.users");
+        assert_eq!(relationships[0].key.references, vec!["id"]);
+
+        let (entities, relationships) = load(&db, Some("main.orders")).await.unwrap();
+        assert_eq!(entities.len(), 2);
+        assert_eq!(relationships.len(), 1);
+    }
+
+    #[test]
+    fn boxes_wrap_to_the_width() {
+        let entities = vec![
+            Entity { name: "users".to_string(), lines: vec!["id PK".to_string()] },
+            Entity { name: "orders".to_string(), lines: vec!["id PK".to_string(), "user_id FK".to_string()] },
+        ];
+        assert_eq!(diagram(&entities, 80), vec![
+            "+-------+   +------------+",
+            "| users |   | orders     |",
+            "+-------+   +------------+",
+            "| id PK |   | id PK      |",
+            "+-------+   | user_id FK |",
+            "            +------------+",
+        ]);
+        assert_eq!(diagram(&entities, 20).len(), 5 + 1 + 6);
+    }
+}
@@ -0,0 +1,270 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use serde_json::{json, Map, Value};
+use sqlx::sqlite::SqliteConnectOptions;
+use sqlx::{Connection, SqliteConnection};
+
+use crate::schema::quote;
+use crate::sql_ops::{ResultSet, SqlValue};
+
+/// The file formats a result set can be exported to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Csv,
+    Json,
+    Ndjson,
+    Markdown,
+    // A new table in a scratch SQLite database
+    Sqlite,
+}
+
+impl Format {
+    pub fn parse(name: &str) -> Result<Format, Box<dyn Error>> {
+        match name.to_lowercase().as_str() {
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            "ndjson" | "jsonl" => Ok(Format::Ndjson),
+            "markdown" | "md" => Ok(Format::Markdown),
+            "sqlite" | "db" => Ok(Format::Sqlite),
+            _ => Err(format!("unknown export format '{}', expected csv, json, ndjson, markdown or sqlite", name).into()),
+        }
+    }
+
+    /// Guesses the format from the extension of `path`, for `sql --out`.
+    pub fn from_path(path: &str) -> Result<Format, Box<dyn Error>> {
+        match Path::new(path).extension().and_then(|extension| extension.to_str()) {
+            Some("sqlite" | "sqlite3") => Ok(Format::Sqlite),
+            Some(extension) => Format::parse(extension),
+            None => Err(format!("can not tell the format of '{}' without an extension", path).into()),
+        }
+    }
+}
+
+/// Writes `result` to `path` and returns a short description of what was written.
+/// Files are never overwritten, SQLite exports add a table to the database instead.
+pub async fn export(result: &ResultSet, format: Format, path: &str) -> Result<String, Box<dyn Error>> {
+    if format != Format::Sqlite && Path::new(path).exists() {
+        return Err(format!("{} already exists", path).into());
+    }
+    let text = match format {
+        Format::Csv => to_csv(result),
+        Format::Json => to_json(result),
+        Format::Ndjson => to_ndjson(result),
+        Format::Markdown => to_markdown(result),
+        Format::Sqlite => {
+            let table = to_sqlite(result, path).await?;
+            return Ok(format!("{} rows to table {} in {}", result.rows.len(), table, path));
+        },
+    };
+    fs::write(path, text)?;
+    Ok(format!("{} rows to {}", result.rows.len(), path))
+}
+
+/// CSV as described by RFC 4180: CRLF line endings, and fields holding a comma, quote or line
+/// break are quoted with their quotes doubled. NULL is written as an empty field.
+pub fn to_csv(result: &ResultSet) -> String {
+    let mut csv = String::new();
+    let header = result.columns.iter().map(|column| csv_field(&column.name)).collect::<Vec<String>>();
+    csv.push_str(&header.join(","));
+    csv.push_str("\r\n");
+    for row in &result.rows {
+        let fields = row.iter().map(|value| csv_field(&text(value))).collect::<Vec<String>>();
+        csv.push_str(&fields.join(","));
+        csv.push_str("\r\n");
+    }
+    csv
+}
+
+/// A JSON array with one object per row, keyed by column name.
+pub fn to_json(result: &ResultSet) -> String {
+    let rows = result.rows.iter().map(|row| row_object(result, row)).collect::<Vec<Value>>();
+    let mut json = serde_json::to_string_pretty(&rows).unwrap_or_default();
+    json.push('\n');
+    json
+}
+
+/// Newline delimited JSON, one object per row.
+pub fn to_ndjson(result: &ResultSet) -> String {
+    result.rows.iter()
+        .map(|row| format!("{}\n", row_object(result, row)))
+        .collect()
+}
+
+/// A GitHub flavored Markdown table, pipes in values are escaped and line breaks become spaces.
+pub fn to_markdown(result: &ResultSet) -> String {
+    let cell = |text: &str| text.replace('|', "\\|").replace(['\r', '\n'], " ");
+    let mut markdown = String::new();
+    let header = result.columns.iter().map(|column| cell(&column.name)).collect::<Vec<String>>();
+    markdown.push_str(&format!("| {} |\n", header.join(" | ")));
+    markdown.push_str(&format!("|{}\n", " --- |".repeat(result.columns.len())));
+    for row in &result.rows {
+        let cells = row.iter().map(|value| cell(&text(value))).collect::<Vec<String>>();
+        markdown.push_str(&format!("| {} |\n", cells.join(" | ")));
+    }
+    markdown
+}
+
+/// Copies `result` into a new table of the SQLite database at `path`, creating the file if needed.
+/// Returns the name of the table, `result`, or `result_2` and so on when it is taken.
+pub async fn to_sqlite(result: &ResultSet, path: &str) -> Result<String, Box<dyn Error>> {
+    let options = SqliteConnectOptions::new().filename(path).create_if_missing(true);
+    let mut connection = SqliteConnection::connect_with(&options).await?;
+
+    let mut table = "result".to_string();
+    let mut number = 1;
+    while sqlx::query("SELECT 1 FROM sqlite_master WHERE name = ?").bind(&table).fetch_optional(&mut connection).await?.is_some() {
+        number += 1;
+        table = format!("result_{}", number);
+    }
+
+    write_table(&mut connection, &table, result).await?;
+    connection.close().await?;
+    Ok(table)
+}
+
+/// Creates `table` with the columns of `result` and inserts its rows, in one transaction.
+/// Columns are renamed by `column_names`, so `SELECT a.id, b.id` can be written.
+pub async fn write_table(connection: &mut SqliteConnection, table: &str, result: &ResultSet) -> Result<(), Box<dyn Error>> {
+    let names = column_names(result.columns.iter().map(|column| column.name.as_str()));
+    let columns = names.iter().zip(&result.columns)
+        .map(|(name, column)| format!("{} {}", quote(name), column.type_name).trim_end().to_string())
+        .collect::<Vec<String>>();
+    let mut transaction = connection.begin().await?;
+    sqlx::query(&format!("CREATE TABLE {} ({})", quote(table), columns.join(", "))).execute(&mut *transaction).await?;
+    let insert = format!("INSERT INTO {} VALUES ({})", quote(table), vec!["?"; result.columns.len()].join(", "));
+    for row in &result.rows {
+        let mut query = sqlx::query(&insert);
+        for value in row {
+            query = match value {
+                SqlValue::Null => query.bind(None::<i64>),
+                SqlValue::Integer(value) => query.bind(*value),
+                SqlValue::Real(value) => query.bind(*value),
+                SqlValue::Text(value) => query.bind(value.clone()),
+                SqlValue::Blob(value) => query.bind(value.clone()),
+            };
+        }
+        query.execute(&mut *transaction).await?;
+    }
+    transaction.commit().await?;
+    Ok(())
+}
+
+/// The full text of a value, blobs are written as hex without the preview cut-off of `Display`.
+fn text(value: &SqlValue) -> String {
+    match value {
+        SqlValue::Null => String::new(),
+        SqlValue::Blob(bytes) => bytes.iter().map(|byte| format!("{:02x}", byte)).collect(),
+        value => value.to_string(),
+    }
+}
+
+fn row_object(result: &ResultSet, row: &[SqlValue]) -> Value {
+    let mut object = Map::new();
+    for (column, value) in result.columns.iter().zip(row) {
+        let value = match value {
+            SqlValue::Null => Value::Null,
+            SqlValue::Integer(value) => json!(value),
+            SqlValue::Real(value) => json!(value),
+            value => json!(text(value)),
+        };
+        object.insert(column.name.clone(), value);
+    }
+    Value::Object(object)
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Names a table can be created with: empty names become `column_3` by position and
+/// repeated ones, compared without case like SQLite does, become `id_2`, `id_3` and so on.
+pub fn column_names<'a>(names: impl Iterator<Item = &'a str>) -> Vec<String> {
+    let mut unique_names: Vec<String> = Vec::new();
+    for (index, name) in names.enumerate() {
+        let name = match name.trim() {
+            "" => format!("column_{}", index + 1),
+            name => name.to_string(),
+        };
+        let mut unique = name.clone();
+        let mut number = 1;
+        while unique_names.iter().any(|taken| taken.eq_ignore_ascii_case(&unique)) {
+            number += 1;
+            unique = format!("{}_{}", name, number);
+        }
+        unique_names.push(unique);
+    }
+    unique_names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql_ops::ResultColumn;
+
+    fn result() -> ResultSet {
+        ResultSet {
+            columns: vec![
+                ResultColumn { name: "id".to_string(), type_name: "INTEGER".to_string() },
+                ResultColumn { name: "note".to_string(), type_name: "TEXT".to_string() },
+                ResultColumn { name: "data".to_string(), type_name: "BLOB".to_string() },
+            ],
+            rows: vec![
+                vec![SqlValue::Integer(1), SqlValue::Text("said \"hi\", then left".to_string()), SqlValue::Blob(vec![0xde, 0xad])],
+                vec![SqlValue::Integer(2), SqlValue::Text("a | b\nc".to_string()), SqlValue::Null],
+            ],
+        }
+    }
+
+    #[test]
+    fn csv_quotes_per_rfc_4180() {
+        assert_eq!(to_csv(&result()), "id,note,data\r\n1,\"said \"\"hi\"\", then left\",dead\r\n2,\"a | b\nc\",\r\n");
+    }
+
+    #[test]
+    fn json_markdown_and_ndjson() {
+        let rows: Value = serde_json::from_str(&to_json(&result())).unwrap();
+        assert_eq!(rows[1], json!({"id": 2, "note": "a | b\nc", "data": null}));
+        assert_eq!(to_ndjson(&result()).lines().count(), 2);
+        assert_eq!(to_markdown(&result()).lines().nth(3), Some("| 2 | a \\| b c |  |"));
+        assert_eq!(Format::from_path("out/results.jsonl").unwrap(), Format::Ndjson);
+        assert!(Format::parse("xlsx").is_err());
+    }
+
+    #[tokio::test]
+    async fn sqlite_exports_go_to_a_new_table() {
+        let path = std::env::temp_dir().join(format!("data_bot2_export_{}.db", std::process::id())).display().to_string();
+        let _ = fs::remove_file(&path);
+
+        assert_eq!(to_sqlite(&result(), &path).await.unwrap(), "result");
+        assert_eq!(to_sqlite(&result(), &path).await.unwrap(), "result_2");
+        let mut connection = SqliteConnection::connect(&path).await.unwrap();
+        let (note, data): (String, Vec<u8>) = sqlx::query_as("SELECT note, data FROM result_2 WHERE id = 1")
+            .fetch_one(&mut connection).await.unwrap();
+        assert_eq!(note, "said \"hi\", then left");
+        assert_eq!(data, vec![0xde, 0xad]);
+
+        let mut repeated = result();
+        repeated.columns[1].name = "ID".to_string();
+        repeated.columns[2].name = "id".to_string();
+        assert_eq!(to_sqlite(&repeated, &path).await.unwrap(), "result_3");
+        let names: Vec<(String,)> = sqlx::query_as("SELECT name FROM pragma_table_info('result_3')")
+            .fetch_all(&mut connection).await.unwrap();
+        assert_eq!(names, vec![("id".to_string(),), ("ID_2".to_string(),), ("id_3".to_string(),)]);
+    }
+
+    #[tokio::test]
+    async fn files_are_not_overwritten() {
+        let path = std::env::temp_dir().join(format!("data_bot2_export_{}.csv", std::process::id())).display().to_string();
+        fs::write(&path, "kept").unwrap();
+
+        assert!(export(&result(), Format::Csv, &path).await.unwrap_err().to_string().contains("already exists"));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "kept");
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,74 @@
+use sqlx::{Pool, Sqlite};
+
+use crate::sql_ops::DataBase;
+
+/// Returns the path of a file in `tests/fixtures`.
+pub fn path(name: &str) -> String {
+    format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+/// Returns the path of the database file created by `database` for a test.
+pub fn database_file(name: &str) -> String {
+    std::env::temp_dir()
+        .join(format!("data_bot2_{}_{}.db", name, std::process::id()))
+        .display()
+        .to_string()
+}
+
+/// Creates a fresh SQLite file from `tests/fixtures/sample.sql` and connects to it.
+/// Each test passes its own name so tests running in parallel never share a file.
+pub async fn database(name: &str) -> Pool<Sqlite> {
+    let file = database_file(name);
+    let _ = std::fs::remove_file(&file);
+    let db = DataBase::create_connection(format!("sqlite://{}?mode=rwc", file).as_str(), false)
+        .await
+        .expect("could not create fixture database");
+    sqlx::query(&std::fs::read_to_string(path("sample.sql")).unwrap())
+        .execute(&db)
+        .await
+        .expect("could not load fixture database");
+    db
+}
+
+/// A stand-in for an OpenAI-compatible server: answers each request it receives with the next
+/// of `responses`, given as a content type and body. Returns the base url to point the client at,
+/// and the requests received as method, path and body once all responses are sent.
+pub async fn http_stub(responses: Vec<(&'static str, String)>) -> (String, tokio::task::JoinHandle<Vec<(String, String)>>) {
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.expect("could not start stub server");
+    let base = format!("http://{}/v1", listener.local_addr().unwrap());
+    let requests = tokio::spawn(async move {
+        let mut requests = Vec::new();
+        for (content_type, body) in responses {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut reader = BufReader::new(stream);
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).await.unwrap();
+            let mut length = 0;
+            loop {
+                let mut header = String::new();
+                reader.read_line(&mut header).await.unwrap();
+                if header.trim().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = header.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut request_body = vec![0; length];
+            reader.read_exact(&mut request_body).await.unwrap();
+            let method_and_path = request_line.split_whitespace().take(2).collect::<Vec<&str>>().join(" ");
+            requests.push((method_and_path, String::from_utf8(request_body).unwrap()));
+
+            let response = format!("HTTP/1.1 200 OK\r\ncontent-type: {}\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                content_type, body.len(), body);
+            reader.get_mut().write_all(response.as_bytes()).await.unwrap();
+            reader.get_mut().shutdown().await.unwrap();
+        }
+        requests
+    });
+    (base, requests)
+}
@@ -0,0 +1,248 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+use serde_json::Value;
+use sqlx::{Connection, Pool, Sqlite};
+
+use crate::export;
+use crate::sql_ops::{DataBase, ResultColumn, ResultSet, SqlValue};
+
+/// Reads a CSV, JSON or NDJSON file into a result set, picking the format by extension.
+pub fn read_file(path: &Path) -> Result<ResultSet, Box<dyn Error>> {
+    let text = fs::read_to_string(path)?;
+    match path.extension().and_then(|extension| extension.to_str()).map(str::to_lowercase).as_deref() {
+        Some("csv") => parse_csv(&text),
+        Some("json") => parse_json(&text),
+        Some("ndjson" | "jsonl") => parse_ndjson(&text),
+        _ => Err(format!("can not import '{}', expected a .csv, .json or .ndjson file", path.display()).into()),
+    }
+}
+
+/// Creates `table` in the database behind `db` and fills it with `result`.
+pub async fn import(db: &Pool<Sqlite>, table: &str, result: &ResultSet) -> Result<(), Box<dyn Error>> {
+    let mut connection = DataBase::write_connection(db).await?;
+    export::write_table(&mut connection, table, result).await?;
+    connection.close().await?;
+    Ok(())
+}
+
+/// The table a file is imported into when none is given: its name without the extension,
+/// with everything but letters, digits and underscores replaced.
+pub fn table_name(path: &Path) -> String {
+    let stem = path.file_stem().map(|stem| stem.to_string_lossy().to_string()).unwrap_or_default();
+    let mut name = stem.chars()
+        .map(|c| if c.is_alphanumeric() || c == '_' { c } else { '_' })
+        .collect::<String>();
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        name.insert_str(0, "t_");
+    }
+    name
+}
+
+/// CSV as described by RFC 4180, the first record holds the column names.
+/// Quoted fields can hold commas, doubled quotes and line breaks, and lines end in CRLF or LF.
+pub fn parse_csv(text: &str) -> Result<ResultSet, Box<dyn Error>> {
+    let mut records = csv_records(text.strip_prefix('\u{feff}').unwrap_or(text))?.into_iter();
+    let header = match records.next() {
+        Some(header) => header,
+        None => return Err("the file is empty".into()),
+    };
+    let records = records.collect::<Vec<Vec<String>>>();
+    for (number, record) in records.iter().enumerate() {
+        if record.len() != header.len() {
+            return Err(format!("record {} has {} fields, the header has {}", number + 2, record.len(), header.len()).into());
+        }
+    }
+    let rows = records.iter().map(|record| record.iter().map(|field| csv_value(field)).collect()).collect();
+    let mut result = infer_types(header, rows);
+    // Text columns keep the fields as they were written, "1.50" stays "1.50"
+    for (index, column) in result.columns.iter().enumerate() {
+        if column.type_name == "TEXT" {
+            for (row, record) in result.rows.iter_mut().zip(&records) {
+                if row[index] != SqlValue::Null {
+                    row[index] = SqlValue::Text(record[index].clone());
+                }
+            }
+        }
+    }
+    Ok(result)
+}
+
+/// A JSON array of objects, one row per object. Columns are the keys of all objects in the
+/// order they are first seen, and keys missing from an object are NULL.
+pub fn parse_json(text: &str) -> Result<ResultSet, Box<dyn Error>> {
+    match serde_json::from_str(text)? {
+        Value::Array(objects) => json_rows(objects),
+        _ => Err("expected a JSON array of objects".into()),
+    }
+}
+
+/// Newline delimited JSON, one object per line.
+pub fn parse_ndjson(text: &str) -> Result<ResultSet, Box<dyn Error>> {
+    let objects = text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(serde_json::from_str)
+        .collect::<Result<Vec<Value>, serde_json::Error>>()?;
+    json_rows(objects)
+}
+
+fn json_rows(objects: Vec<Value>) -> Result<ResultSet, Box<dyn Error>> {
+    let mut names: Vec<String> = Vec::new();
+    for object in &objects {
+        match object {
+            Value::Object(object) => for key in object.keys() {
+                if !names.contains(key) {
+                    names.push(key.clone());
+                }
+            },
+            _ => return Err("expected every row to be a JSON object".into()),
+        }
+    }
+    let rows = objects.iter()
+        .map(|object| names.iter().map(|name| json_value(object.get(name).unwrap_or(&Value::Null))).collect())
+        .collect();
+    Ok(infer_types(names, rows))
+}
+
+fn json_value(value: &Value) -> SqlValue {
+    match value {
+        Value::Null => SqlValue::Null,
+        Value::Bool(value) => SqlValue::Integer(*value as i64),
+        Value::Number(number) => match number.as_i64() {
+            Some(value) => SqlValue::Integer(value),
+            None => number.as_f64().map(SqlValue::Real).unwrap_or_else(|| SqlValue::Text(number.to_string())),
+        },
+        Value::String(value) => SqlValue::Text(value.clone()),
+        // Nested arrays and objects are kept as JSON text, sqlite's json functions can read them
+        value => SqlValue::Text(value.to_string()),
+    }
+}
+
+fn csv_records(text: &str) -> Result<Vec<Vec<String>>, Box<dyn Error>> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            },
+            (true, '"') => quoted = false,
+            (true, c) => field.push(c),
+            (false, '"') if field.is_empty() => quoted = true,
+            (false, ',') => record.push(std::mem::take(&mut field)),
+            (false, '\r') if chars.peek() == Some(&'\n') => {},
+            (false, '\n') => {
+                record.push(std::mem::take(&mut field));
+                // Blank lines are skipped
+                if record != [""] {
+                    records.push(std::mem::take(&mut record));
+                }
+                record.clear();
+            },
+            (false, c) => field.push(c),
+        }
+    }
+    if quoted {
+        return Err("a quoted field is not closed".into());
+    }
+    // The last line does not need a line break
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// The value of a CSV field: empty fields are NULL and numbers are numbers, except those with
+/// leading zeros such as zip codes or phone numbers, which would lose them.
+fn csv_value(field: &str) -> SqlValue {
+    let digits = field.trim_start_matches(['-', '+']);
+    if field.is_empty() {
+        SqlValue::Null
+    } else if digits.len() > 1 && digits.starts_with('0') && !digits.starts_with("0.") {
+        SqlValue::Text(field.to_string())
+    } else if let Ok(value) = field.parse::<i64>() {
+        SqlValue::Integer(value)
+    } else if let Some(value) = field.parse::<f64>().ok().filter(|value| value.is_finite() && field.contains(|c: char| c.is_ascii_digit())) {
+        SqlValue::Real(value)
+    } else {
+        SqlValue::Text(field.to_string())
+    }
+}
+
+/// Gives each column one type: INTEGER when all its values are integers, REAL when they are
+/// numbers, TEXT otherwise, converting the values to match. Empty and duplicate names are replaced.
+fn infer_types(names: Vec<String>, mut rows: Vec<Vec<SqlValue>>) -> ResultSet {
+    let mut columns: Vec<ResultColumn> = Vec::new();
+    for (index, name) in export::column_names(names.iter().map(String::as_str)).into_iter().enumerate() {
+
+        let values = rows.iter().map(|row| &row[index]).filter(|value| **value != SqlValue::Null);
+        let type_name = values.fold("INTEGER", |type_name, value| match (type_name, value) {
+            ("INTEGER", SqlValue::Integer(_)) => "INTEGER",
+            ("INTEGER" | "REAL", SqlValue::Integer(_) | SqlValue::Real(_)) => "REAL",
+            _ => "TEXT",
+        });
+        for row in rows.iter_mut() {
+            row[index] = match (type_name, &row[index]) {
+                (_, SqlValue::Null) => SqlValue::Null,
+                ("REAL", SqlValue::Integer(value)) => SqlValue::Real(*value as f64),
+                ("TEXT", SqlValue::Integer(value)) => SqlValue::Text(value.to_string()),
+                ("TEXT", SqlValue::Real(value)) => SqlValue::Text(value.to_string()),
+                (_, value) => value.clone(),
+            };
+        }
+        columns.push(ResultColumn { name, type_name: type_name.to_string() });
+    }
+    ResultSet { columns, rows }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn types(result: &ResultSet) -> Vec<&str> {
+        result.columns.iter().map(|column| column.type_name.as_str()).collect()
+    }
+
+    #[test]
+    fn csv_fields_are_unquoted_and_typed() {
+        let result = parse_csv("id,name,price,zip,\r\n1,\"Smith, \"\"Jo\"\"\",2,01234,x\r\n2,\"two\nlines\",2.5,,1.50").unwrap();
+        assert_eq!(result.columns[4].name, "column_5");
+        assert_eq!(types(&result), vec!["INTEGER", "TEXT", "REAL", "TEXT", "TEXT"]);
+        assert_eq!(result.rows[0][1], SqlValue::Text("Smith, \"Jo\"".to_string()));
+        assert_eq!(result.rows[0][2], SqlValue::Real(2.0));
+        assert_eq!(result.rows[0][3], SqlValue::Text("01234".to_string()));
+        assert_eq!(result.rows[1][1], SqlValue::Text("two\nlines".to_string()));
+        assert_eq!(result.rows[1][3], SqlValue::Null);
+        assert_eq!(result.rows[1][4], SqlValue::Text("1.50".to_string()));
+        assert!(parse_csv("a,b\n1\n").is_err());
+    }
+
+    #[test]
+    fn json_keys_become_columns() {
+        let result = parse_json(r#"[{"id": 1, "tags": ["a"], "ok": true}, {"id": 2, "score": 1.5, "ok": false}, {"id": "three"}]"#).unwrap();
+        let names = result.columns.iter().map(|column| column.name.as_str()).collect::<Vec<&str>>();
+        assert_eq!(names, vec!["id", "tags", "ok", "score"]);
+        assert_eq!(types(&result), vec!["TEXT", "TEXT", "INTEGER", "REAL"]);
+        assert_eq!(result.rows[0][0], SqlValue::Text("1".to_string()));
+        assert_eq!(result.rows[0][1], SqlValue::Text("[\"a\"]".to_string()));
+        assert_eq!(result.rows[2][2], SqlValue::Null);
+        assert_eq!(parse_ndjson("{\"a\": 1}\n\n{\"a\": 2}\n").unwrap().rows.len(), 2);
+        assert_eq!(table_name(Path::new("exports/2024 sales.csv")), "t_2024_sales");
+    }
+
+    #[tokio::test]
+    async fn imported_tables_can_be_queried() {
+        let db = DataBase::create_connection("sqlite::memory:", false).await.unwrap();
+        import(&db, "prices", &parse_csv("item,price\nbread,2.5\nmilk,1\n").unwrap()).await.unwrap();
+
+        let (total,): (f64,) = sqlx::query_as("SELECT sum(price) FROM prices").fetch_one(&db).await.unwrap();
+        assert_eq!(total, 3.5);
+        assert!(import(&db, "prices", &parse_csv("item\ntea\n").unwrap()).await.is_err());
+    }
+}
@@ -0,0 +1,287 @@
+use std::collections::HashMap;
+use std::error::Error;
+
+use crossterm::event::{KeyCode, KeyModifiers};
+
+/// Something the line editor does in response to a key. Typed characters that are not bound
+/// to an action are inserted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    AcceptLine,
+    // Ends the prompt with an empty line, Ctrl-C in a shell
+    AbandonLine,
+    // Cancels a history search
+    Abort,
+    BackwardChar,
+    ForwardChar,
+    BeginningOfLine,
+    EndOfLine,
+    BackwardWord,
+    ForwardWord,
+    BackwardDeleteChar,
+    DeleteChar,
+    // Ends the input on an empty line, deletes a character otherwise, Ctrl-D in a shell
+    EndOfFile,
+    KillLine,
+    UnixLineDiscard,
+    UnixWordRubout,
+    BackwardKillWord,
+    KillWord,
+    Yank,
+    YankPop,
+    TransposeChars,
+    PreviousHistory,
+    NextHistory,
+    ReverseSearchHistory,
+    // Leaves insert mode for vi's normal mode
+    ViMovementMode,
+}
+
+// The names used in the config file, the same as readline's where it has the action
+const ACTIONS: &[(&str, Action)] = &[
+    ("accept-line", Action::AcceptLine),
+    ("abandon-line", Action::AbandonLine),
+    ("abort", Action::Abort),
+    ("backward-char", Action::BackwardChar),
+    ("forward-char", Action::ForwardChar),
+    ("beginning-of-line", Action::BeginningOfLine),
+    ("end-of-line", Action::EndOfLine),
+    ("backward-word", Action::BackwardWord),
+    ("forward-word", Action::ForwardWord),
+    ("backward-delete-char", Action::BackwardDeleteChar),
+    ("delete-char", Action::DeleteChar),
+    ("end-of-file", Action::EndOfFile),
+    ("kill-line", Action::KillLine),
+    ("unix-line-discard", Action::UnixLineDiscard),
+    ("unix-word-rubout", Action::UnixWordRubout),
+    ("backward-kill-word", Action::BackwardKillWord),
+    ("kill-word", Action::KillWord),
+    ("yank", Action::Yank),
+    ("yank-pop", Action::YankPop),
+    ("transpose-chars", Action::TransposeChars),
+    ("previous-history", Action::PreviousHistory),
+    ("next-history", Action::NextHistory),
+    ("reverse-search-history", Action::ReverseSearchHistory),
+    ("vi-movement-mode", Action::ViMovementMode),
+];
+
+impl Action {
+    pub fn parse(name: &str) -> Result<Action, Box<dyn Error>> {
+        match ACTIONS.iter().find(|(action_name, _)| *action_name == name) {
+            Some((_, action)) => Ok(*action),
+            None => Err(format!("unknown editor action '{}'", name).into()),
+        }
+    }
+}
+
+/// Which keys do what in the line editor.
+#[derive(Debug, Clone)]
+pub struct KeyMap {
+    bindings: HashMap<(KeyModifiers, KeyCode), Action>,
+}
+
+impl KeyMap {
+    /// A preset by name, `emacs` or `vi`.
+    pub fn preset(name: &str) -> Result<KeyMap, Box<dyn Error>> {
+        match name {
+            "emacs" => Ok(KeyMap::emacs()),
+            "vi" => Ok(KeyMap::vi()),
+            _ => Err(format!("unknown key map '{}', expected emacs or vi", name).into()),
+        }
+    }
+
+    /// The readline defaults.
+    pub fn emacs() -> KeyMap {
+        KeyMap::with(&[
+            ("enter", Action::AcceptLine),
+            ("ctrl-c", Action::AbandonLine),
+            ("ctrl-g", Action::Abort),
+            ("left", Action::BackwardChar),
+            ("ctrl-b", Action::BackwardChar),
+            ("right", Action::ForwardChar),
+            ("ctrl-f", Action::ForwardChar),
+            ("home", Action::BeginningOfLine),
+            ("ctrl-a", Action::BeginningOfLine),
+            ("end", Action::EndOfLine),
+            ("ctrl-e", Action::EndOfLine),
+            ("alt-b", Action::BackwardWord),
+            ("ctrl-left", Action::BackwardWord),
+            ("alt-f", Action::ForwardWord),
+            ("ctrl-right", Action::ForwardWord),
+            ("backspace", Action::BackwardDeleteChar),
+            ("ctrl-h", Action::BackwardDeleteChar),
+            ("delete", Action::DeleteChar),
+            ("ctrl-d", Action::EndOfFile),
+            ("ctrl-k", Action::KillLine),
+            ("ctrl-u", Action::UnixLineDiscard),
+            ("ctrl-w", Action::UnixWordRubout),
+            ("alt-backspace", Action::BackwardKillWord),
+            ("alt-d", Action::KillWord),
+            ("ctrl-y", Action::Yank),
+            ("alt-y", Action::YankPop),
+            ("ctrl-t", Action::TransposeChars),
+            ("up", Action::PreviousHistory),
+            ("ctrl-p", Action::PreviousHistory),
+            ("down", Action::NextHistory),
+            ("ctrl-n", Action::NextHistory),
+            ("ctrl-r", Action::ReverseSearchHistory),
+        ])
+    }
+
+    /// The keys of vi's insert mode, with the arrow keys and history search kept.
+    /// Escape goes to normal mode, where keys are vi commands.
+    pub fn vi() -> KeyMap {
+        KeyMap::with(&[
+            ("esc", Action::ViMovementMode),
+            ("enter", Action::AcceptLine),
+            ("ctrl-c", Action::AbandonLine),
+            ("ctrl-g", Action::Abort),
+            ("left", Action::BackwardChar),
+            ("right", Action::ForwardChar),
+            ("home", Action::BeginningOfLine),
+            ("end", Action::EndOfLine),
+            ("ctrl-left", Action::BackwardWord),
+            ("ctrl-right", Action::ForwardWord),
+            ("backspace", Action::BackwardDeleteChar),
+            ("ctrl-h", Action::BackwardDeleteChar),
+            ("delete", Action::DeleteChar),
+            ("ctrl-d", Action::EndOfFile),
+            ("ctrl-u", Action::UnixLineDiscard),
+            ("ctrl-w", Action::UnixWordRubout),
+            ("up", Action::PreviousHistory),
+            ("down", Action::NextHistory),
+            ("ctrl-r", Action::ReverseSearchHistory),
+        ])
+    }
+
+    fn with(bindings: &[(&str, Action)]) -> KeyMap {
+        KeyMap {
+            bindings: bindings.iter()
+                .map(|(key, action)| (parse_key(key).expect("preset keys are valid"), *action))
+                .collect(),
+        }
+    }
+
+    /// Binds a key such as `ctrl-x` or `alt-left` to the action with the given name,
+    /// `none` removes the binding.
+    pub fn bind(&mut self, key: &str, action: &str) -> Result<(), Box<dyn Error>> {
+        let key = parse_key(key)?;
+        match action {
+            "none" => self.bindings.remove(&key),
+            action => self.bindings.insert(key, Action::parse(action)?),
+        };
+        Ok(())
+    }
+
+    /// Whether a key leads to vi's normal mode, so the editor has modes.
+    pub fn modal(&self) -> bool {
+        self.bindings.values().any(|action| *action == Action::ViMovementMode)
+    }
+
+    pub fn get(&self, modifiers: KeyModifiers, code: KeyCode) -> Option<Action> {
+        // Terminals report shifted letters with and without the shift modifier
+        let modifiers = match code {
+            KeyCode::Char(_) => modifiers - KeyModifiers::SHIFT,
+            _ => modifiers,
+        };
+        self.bindings.get(&(modifiers, code)).copied()
+    }
+}
+
+impl Default for KeyMap {
+    fn default() -> KeyMap {
+        KeyMap::emacs()
+    }
+}
+
+/// Reads a key written as modifiers and a key name joined by dashes, like `ctrl-alt-k` or `shift-tab`.
+pub fn parse_key(key: &str) -> Result<(KeyModifiers, KeyCode), Box<dyn Error>> {
+    let invalid = || format!("unknown key '{}'", key);
+    // The last part is the key itself, which may be a dash
+    let (modifiers, name) = match key.strip_suffix("--") {
+        Some(modifiers) => (modifiers, "-"),
+        None => key.rsplit_once('-').unwrap_or(("", key)),
+    };
+    // Names are read without case, but a single character is the character typed: `A` is not `a`
+    let name = match name.chars().count() {
+        1 => name.to_string(),
+        _ => name.to_lowercase(),
+    };
+    let mut parsed = KeyModifiers::NONE;
+    for modifier in modifiers.to_lowercase().split('-').filter(|modifier| !modifier.is_empty()) {
+        parsed |= match modifier {
+            "ctrl" | "control" | "c" => KeyModifiers::CONTROL,
+            "alt" | "meta" | "m" => KeyModifiers::ALT,
+            "shift" | "s" => KeyModifiers::SHIFT,
+            _ => return Err(invalid().into()),
+        };
+    }
+    let code = match name.as_str() {
+        "enter" | "return" => KeyCode::Enter,
+        "backspace" => KeyCode::Backspace,
+        "delete" | "del" => KeyCode::Delete,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        "insert" => KeyCode::Insert,
+        "tab" => KeyCode::Tab,
+        "esc" | "escape" => KeyCode::Esc,
+        "space" => KeyCode::Char(' '),
+        name if name.starts_with('f') && name[1..].parse::<u8>().is_ok() => KeyCode::F(name[1..].parse().unwrap()),
+        name if name.chars().count() == 1 => KeyCode::Char(name.chars().next().unwrap()),
+        _ => return Err(invalid().into()),
+    };
+    // Shifted letters arrive as the upper case letter, `shift-a` is `A`
+    match code {
+        KeyCode::Char(c) if parsed.contains(KeyModifiers::SHIFT) => {
+            Ok((parsed - KeyModifiers::SHIFT, KeyCode::Char(c.to_uppercase().next().unwrap_or(c))))
+        },
+        code => Ok((parsed, code)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_are_read_from_their_names() {
+        assert_eq!(parse_key("ctrl-a").unwrap(), (KeyModifiers::CONTROL, KeyCode::Char('a')));
+        assert_eq!(parse_key("Ctrl-Alt-Left").unwrap(), (KeyModifiers::CONTROL | KeyModifiers::ALT, KeyCode::Left));
+        assert_eq!(parse_key("alt--").unwrap(), (KeyModifiers::ALT, KeyCode::Char('-')));
+        assert_eq!(parse_key("f5").unwrap(), (KeyModifiers::NONE, KeyCode::F(5)));
+        assert!(parse_key("hyper-x").is_err());
+        assert!(parse_key("ctrl-enterr").is_err());
+        assert_eq!(parse_key("A").unwrap(), (KeyModifiers::NONE, KeyCode::Char('A')));
+        assert_eq!(parse_key("Shift-a").unwrap(), (KeyModifiers::NONE, KeyCode::Char('A')));
+        assert_eq!(parse_key("ALT-x").unwrap(), (KeyModifiers::ALT, KeyCode::Char('x')));
+    }
+
+    #[test]
+    fn bindings_override_the_preset() {
+        let mut keymap = KeyMap::preset("emacs").unwrap();
+        assert_eq!(keymap.get(KeyModifiers::CONTROL, KeyCode::Char('k')), Some(Action::KillLine));
+        assert_eq!(keymap.get(KeyModifiers::SHIFT, KeyCode::Right), None);
+        assert_eq!(keymap.get(KeyModifiers::CONTROL, KeyCode::Char('d')), Some(Action::EndOfFile));
+
+        keymap.bind("ctrl-k", "none").unwrap();
+        keymap.bind("ctrl-x", "unix-line-discard").unwrap();
+        assert_eq!(keymap.get(KeyModifiers::CONTROL, KeyCode::Char('k')), None);
+        assert_eq!(keymap.get(KeyModifiers::CONTROL, KeyCode::Char('x')), Some(Action::UnixLineDiscard));
+        assert!(keymap.bind("ctrl-x", "self-destruct").is_err());
+        // Terminals report upper case letters with the shift modifier
+        keymap.bind("A", "beginning-of-line").unwrap();
+        assert_eq!(keymap.get(KeyModifiers::SHIFT, KeyCode::Char('A')), Some(Action::BeginningOfLine));
+        assert_eq!(keymap.get(KeyModifiers::NONE, KeyCode::Char('a')), None);
+
+        let vi = KeyMap::preset("vi").unwrap();
+        assert_eq!(vi.get(KeyModifiers::CONTROL, KeyCode::Char('a')), None);
+        assert!(vi.modal() && !keymap.modal());
+        assert!(KeyMap::preset("nano").is_err());
+    }
+}
@@ -1,24 +1,94 @@
-use std::io::{stdout, Stdout};
+use std::collections::VecDeque;
+use std::fs;
+use std::io::{self, stdout, Write};
+use std::path::PathBuf;
+use std::sync::OnceLock;
 
 use crossterm::cursor;
-use crossterm::event::{self, Event, KeyCode, KeyModifiers};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyModifiers};
 use crossterm::style::{Print, SetForegroundColor, Color};
 use crossterm::terminal;
 use crossterm::{execute, queue};
 
+use crate::keymap::{Action, KeyMap};
+
+// The key map of every prompt, set once at startup from the config
+static KEYMAP: OnceLock<KeyMap> = OnceLock::new();
+
+// Killed text older than this many kills is forgotten
+const KILL_RING_SIZE: usize = 16;
+
 #[derive(Default)]
 pub struct LineBuffer {
     buffer: String,
     cursor_index: usize,
+    // Text removed by the kill commands, newest last, brought back by yanking
+    kill_ring: Vec<String>,
+    // The text inserted by the last yank, which a yank-pop replaces
+    yanked: Option<Yank>,
+    // Earlier states of the line and cursor, newest last, for vi's undo
+    undo: Vec<(String, usize)>,
+}
+
+/// Where the last yank put its text, in characters, and which kill it was.
+#[derive(Clone, Copy)]
+struct Yank {
+    start: usize,
+    width: usize,
+    ring_index: usize,
+}
+
+/// Colors the text of the line buffer, the spans must add up to the text they were given.
+pub type Highlighter = fn(&str) -> Vec<(Color, String)>;
+
+/// Lines entered at the main prompt, oldest first. Only the most recent `capacity` lines are kept,
+/// and a line entered again moves to the end instead of being stored twice.
+pub struct History {
+    entries: VecDeque<String>,
+    capacity: usize,
+    // File the history is written to after every change, one line per entry
+    path: Option<PathBuf>,
+}
+
+/// Up/Down navigation through the history during one prompt. The line being typed is kept
+/// as a draft and comes back when moving down past the newest entry.
+#[derive(Default)]
+pub struct Recall {
+    entries: Vec<String>,
+    // Equal to the number of entries while the draft is being edited
+    index: usize,
+    draft: String,
+}
+
+/// A Ctrl-R search in progress, shown in place of the prompt.
+pub struct Search {
+    query: String,
+    // Index into the recalled entries of the line shown, `None` until something matched
+    matched: Option<usize>,
+    // The query does not match anything older than the line shown
+    failed: bool,
+    // The line from before the search, put back when it is cancelled
+    original: String,
 }
 
 pub struct Context {
-    // Stdout is stored to prevent needing to call `std::io::stdout()` repeatedly
-    stdout: Stdout,
+    // Stdout is stored to prevent needing to call `std::io::stdout()` repeatedly,
+    // tests draw into a buffer instead
+    stdout: Box<dyn Write>,
+    // The prompt is kept so it can be redrawn after being replaced by the search prompt
+    prompt: Vec<(Color, String)>,
+    // Optional syntax highlighting, applied every time the buffer is redrawn
+    highlighter: Option<Highlighter>,
+    keymap: &'static KeyMap,
+    recall: Recall,
+    search: Option<Search>,
+    // Set when the line was ended by the end-of-input key rather than accepted
+    end_of_input: bool,
+    // Present when the key map has a key for vi's normal mode
+    vi: Option<Vi>,
     // The prompt width is needed to accurately calculate the cursor position
     prompt_width: usize,
-    // The terminal size is neededed for almost all calculations
-    // $ This will need to be continuously updated once resizing is supported
+    // The terminal size is neededed for almost all calculations, it is read again on resize
     terminal_width: usize,
     terminal_height: usize,
     scroll: ScrollState,
@@ -26,7 +96,7 @@ pub struct Context {
 
 /// Represents the amount of scrolling that has occurred so far.
 /// This information is crucial for calculations relating to the cursor position.
-#[derive(Clone)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum ScrollState {
     // The y origin is the original y-coordinate of the prompt, which will be offset by the scroll
     Unscrolled { y_origin: usize },
@@ -34,22 +104,179 @@ pub enum ScrollState {
     // to determine the position of the cursor and the viewport
     Scrolled { y_origin: usize, scroll: usize },
     // Once the editor scrolls past the prompt, the original prompt position becomes irrelevant to
-    // position calculations, so the scroll here represents the number of lines off-screen
+    // position calculations, so the scroll here represents the number of lines above the screen.
+    // The screen follows the cursor, so after moving back the line can also go on below it
     ScrolledPastPrompt { scroll: usize },
 }
 
+impl Context {
+    pub fn new(prompt: Vec<(Color, String)>, highlighter: Option<Highlighter>, recall: Recall) -> Context {
+        let (terminal_width, terminal_height) = terminal::size().unwrap();
+        let mut ctx = Context {
+            stdout: Box::new(stdout()),
+            prompt_width: 0,
+            prompt,
+            highlighter,
+            keymap: KEYMAP.get_or_init(KeyMap::default),
+            recall,
+            search: None,
+            end_of_input: false,
+            vi: match KEYMAP.get_or_init(KeyMap::default).modal() {
+                true => Some(Vi::default()),
+                false => None,
+            },
+            terminal_width: terminal_width as usize,
+            terminal_height: terminal_height as usize,
+            scroll: ScrollState::Unscrolled {
+                y_origin: cursor::position().unwrap().1 as usize,
+            },
+        };
+        ctx.prompt_width = ctx.prompt_spans().iter().map(|(_, text)| text.chars().count()).sum();
+        ctx
+    }
+
+    /// The prompt as it is shown: the search prompt while searching, the one given otherwise,
+    /// after the vi mode when editing like vi.
+    pub fn prompt_spans(&self) -> Vec<(Color, String)> {
+        let mut spans = match self.vi.as_ref().map(Vi::mode) {
+            Some(ViMode::Insert) => vec![(Color::DarkGrey, "(ins) ".to_string())],
+            Some(ViMode::Normal) => vec![(Color::Yellow, "(cmd) ".to_string())],
+            None => Vec::new(),
+        };
+        match self.search {
+            Some(ref search) => spans.extend([
+                (Color::DarkGrey, format!("({}reverse-i-search)`", if search.failed { "failed " } else { "" })),
+                (Color::Reset, search.query.clone()),
+                (Color::DarkGrey, "': ".to_string()),
+            ]),
+            None => spans.extend(self.prompt.iter().cloned()),
+        }
+        spans
+    }
+}
+
+impl History {
+    pub fn new(capacity: usize) -> History {
+        History {
+            entries: VecDeque::new(),
+            capacity,
+            path: None,
+        }
+    }
+
+    /// Reads the history kept in `path`, a file that does not exist yet is an empty history.
+    pub fn load(path: Option<PathBuf>, capacity: usize) -> io::Result<History> {
+        let mut history = History::new(capacity);
+        if let Some(ref path) = path {
+            match fs::read_to_string(path) {
+                Ok(text) => text.lines().for_each(|line| history.push(line)),
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {},
+                Err(e) => return Err(e),
+            }
+        }
+        history.path = path;
+        Ok(history)
+    }
+
+    /// Adds a line and writes the history to its file. Blank lines are not kept.
+    pub fn add(&mut self, line: &str) -> io::Result<()> {
+        if line.trim().is_empty() {
+            return Ok(());
+        }
+        self.push(line);
+        self.save()
+    }
+
+    pub fn clear(&mut self) -> io::Result<()> {
+        self.entries.clear();
+        self.save()
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &str> {
+        self.entries.iter().map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn push(&mut self, line: &str) {
+        let line = line.trim();
+        if line.is_empty() {
+            return;
+        }
+        self.entries.retain(|entry| entry != line);
+        self.entries.push_back(line.to_string());
+        while self.entries.len() > self.capacity {
+            self.entries.pop_front();
+        }
+    }
+
+    fn save(&self) -> io::Result<()> {
+        let path = match self.path {
+            Some(ref path) => path,
+            None => return Ok(()),
+        };
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let text = self.entries.iter().map(|entry| format!("{}\n", entry)).collect::<String>();
+        fs::write(path, text)
+    }
+}
+
+impl Recall {
+    pub fn new(history: &History) -> Recall {
+        Recall {
+            entries: history.iter().map(str::to_string).collect(),
+            index: history.len(),
+            draft: String::new(),
+        }
+    }
+
+    /// The entry before the one shown, saving `current` as the draft when leaving it.
+    pub fn older(&mut self, current: &str) -> Option<&str> {
+        if self.index == 0 {
+            return None;
+        }
+        if self.index == self.entries.len() {
+            self.draft = current.to_string();
+        }
+        self.index -= 1;
+        Some(&self.entries[self.index])
+    }
+
+    /// The newest entry before `before` that contains `query`, with the character offset of the match.
+    pub fn search(&self, query: &str, before: usize) -> Option<(usize, usize)> {
+        self.entries[..before.min(self.entries.len())].iter()
+            .enumerate()
+            .rev()
+            .find_map(|(index, entry)| entry.rfind(query).map(|start| (index, entry[..start].chars().count())))
+    }
+
+    /// The entry after the one shown, or the draft after the newest entry.
+    pub fn newer(&mut self) -> Option<&str> {
+        if self.index >= self.entries.len() {
+            return None;
+        }
+        self.index += 1;
+        Some(self.entries.get(self.index).unwrap_or(&self.draft))
+    }
+}
+
 impl LineBuffer {
     pub fn insert(&mut self, c: char) {
-        // TODO: Add bounds check? Maybe unnecessary
-        self.buffer.insert(self.cursor_index, c);
+        self.buffer.insert(self.byte_index(self.cursor_index), c);
         self.right();
     }
 
     pub fn insert_str(&mut self, s: &str) {
-        self.buffer.insert_str(self.cursor_index, s);
-        for _ in 0..s.chars().count() {
-            self.right();
-        }
+        self.buffer.insert_str(self.byte_index(self.cursor_index), s);
+        self.cursor_index += s.chars().count();
     }
 
     pub fn left(&mut self) {
@@ -66,6 +293,30 @@ impl LineBuffer {
         self.cursor_index = self.cursor_index.saturating_add(1);
     }
 
+    pub fn home(&mut self) {
+        self.cursor_index = 0;
+    }
+
+    pub fn end(&mut self) {
+        self.cursor_index = self.width();
+    }
+
+    /// Moves to the start of the word before the cursor, words are runs of letters and digits.
+    pub fn word_left(&mut self) {
+        self.cursor_index = self.word_start(is_word_char);
+    }
+
+    /// Moves to the end of the word after the cursor.
+    pub fn word_right(&mut self) {
+        self.cursor_index = self.word_end();
+    }
+
+    /// Replaces the whole line, leaving the cursor at its end.
+    pub fn replace(&mut self, s: &str) {
+        self.buffer = s.to_string();
+        self.cursor_index = self.width();
+    }
+
     pub fn backspace(&mut self) {
         // Backspace should do nothing if the cursor is at the start of the line
         if self.cursor_index == 0 {
@@ -77,7 +328,83 @@ impl LineBuffer {
     }
 
     pub fn delete(&mut self) {
-        self.buffer.remove(self.cursor_index);
+        // Delete should do nothing if the cursor is at the end of the line
+        if self.cursor_index == self.width() {
+            return;
+        }
+
+        self.buffer.remove(self.byte_index(self.cursor_index));
+    }
+
+    /// Swaps the characters on either side of the cursor and moves past them,
+    /// at the end of the line the last two characters are swapped.
+    pub fn transpose(&mut self) {
+        let width = self.width();
+        if self.cursor_index == 0 || width < 2 {
+            return;
+        }
+        let index = self.cursor_index.min(width - 1);
+        let mut chars = self.buffer.chars().collect::<Vec<char>>();
+        chars.swap(index - 1, index);
+        self.buffer = chars.into_iter().collect();
+        self.cursor_index = index + 1;
+    }
+
+    /// Kills from the cursor to the end of the line.
+    pub fn kill_to_end(&mut self) {
+        self.kill(self.cursor_index, self.width());
+    }
+
+    /// Kills from the start of the line to the cursor.
+    pub fn kill_to_start(&mut self) {
+        self.kill(0, self.cursor_index);
+    }
+
+    /// Kills back to the previous whitespace, like Ctrl-W in a shell.
+    pub fn kill_word_left(&mut self) {
+        self.kill(self.word_start(|c| !c.is_whitespace()), self.cursor_index);
+    }
+
+    /// Kills back to the start of the word before the cursor.
+    pub fn kill_word_back(&mut self) {
+        self.kill(self.word_start(is_word_char), self.cursor_index);
+    }
+
+    /// Kills forward to the end of the word after the cursor.
+    pub fn kill_word_right(&mut self) {
+        self.kill(self.cursor_index, self.word_end());
+    }
+
+    /// Inserts the most recently killed text.
+    pub fn yank(&mut self) {
+        let text = match self.kill_ring.last() {
+            Some(text) => text.clone(),
+            None => return,
+        };
+        self.yanked = Some(Yank {
+            start: self.cursor_index,
+            width: text.chars().count(),
+            ring_index: self.kill_ring.len() - 1,
+        });
+        self.insert_str(&text);
+    }
+
+    /// Right after a yank, replaces the yanked text with the text killed before it.
+    pub fn yank_pop(&mut self) {
+        let yank = match self.yanked.take() {
+            Some(yank) => yank,
+            None => return,
+        };
+        let ring_index = (yank.ring_index + self.kill_ring.len() - 1) % self.kill_ring.len();
+        let text = self.kill_ring[ring_index].clone();
+        let range = self.byte_index(yank.start)..self.byte_index(yank.start + yank.width);
+        self.buffer.replace_range(range, &text);
+        self.cursor_index = yank.start + text.chars().count();
+        self.yanked = Some(Yank {
+            start: yank.start,
+            width: text.chars().count(),
+            ring_index,
+        });
     }
 
     pub fn width(&self) -> usize {
@@ -94,146 +421,611 @@ impl LineBuffer {
         ciel_div(true_width, ctx.terminal_width)
     }
 
-    pub fn segment(&self, scroll: ScrollState, terminal_width: usize) -> &str {
-        if let ScrollState::ScrolledPastPrompt { scroll } = scroll {
-            let chars_off_screen = terminal_width * scroll;
-            // $ Needs to be changed to some sort of safe slicing
-            &self.buffer[chars_off_screen..]
+    /// The part of the buffer on screen, which is all of it until the editor scrolls past the prompt.
+    pub fn segment(&self, ctx: &Context) -> &str {
+        if let ScrollState::ScrolledPastPrompt { scroll } = ctx.scroll {
+            // The screen starts `scroll` rows into the prompt and buffer, and the line may go on
+            // below it when the cursor was moved up
+            let screen_start = ctx.terminal_width * scroll;
+            let start = screen_start.max(ctx.prompt_width) - ctx.prompt_width;
+            let end = screen_start + ctx.terminal_width * ctx.terminal_height - ctx.prompt_width;
+            &self.buffer[self.byte_index(start)..self.byte_index(end.min(self.width()))]
         } else {
             &self.buffer
         }
     }
+
+    /// Remembers the line so `undo` can bring it back.
+    pub fn checkpoint(&mut self) {
+        self.undo.push((self.buffer.clone(), self.cursor_index));
+    }
+
+    pub fn undo(&mut self) {
+        if let Some((buffer, cursor_index)) = self.undo.pop() {
+            self.buffer = buffer;
+            self.cursor_index = cursor_index;
+        }
+    }
+
+    /// Keeps the cursor on a character, as in vi's normal mode.
+    pub fn clamp_to_last(&mut self) {
+        self.cursor_index = self.cursor_index.min(self.width().saturating_sub(1));
+    }
+
+    /// The start of the next vi word, vi's `w`.
+    pub fn vi_word_forward(&self) -> usize {
+        let chars = self.buffer.chars().collect::<Vec<char>>();
+        let mut index = self.run_end();
+        while index < chars.len() && vi_class(chars[index]) == 0 {
+            index += 1;
+        }
+        index
+    }
+
+    /// The start of the vi word before the cursor, vi's `b`.
+    pub fn vi_word_back(&self) -> usize {
+        let chars = self.buffer.chars().collect::<Vec<char>>();
+        let mut index = self.cursor_index.min(chars.len());
+        while index > 0 && vi_class(chars[index - 1]) == 0 {
+            index -= 1;
+        }
+        let class = match index {
+            0 => return 0,
+            _ => vi_class(chars[index - 1]),
+        };
+        while index > 0 && vi_class(chars[index - 1]) == class {
+            index -= 1;
+        }
+        index
+    }
+
+    /// The last character of the vi word after the cursor, vi's `e`.
+    pub fn vi_word_end(&self) -> usize {
+        let chars = self.buffer.chars().collect::<Vec<char>>();
+        let mut index = self.cursor_index + 1;
+        while index < chars.len() && vi_class(chars[index]) == 0 {
+            index += 1;
+        }
+        if index >= chars.len() {
+            return chars.len().saturating_sub(1);
+        }
+        let class = vi_class(chars[index]);
+        while index + 1 < chars.len() && vi_class(chars[index + 1]) == class {
+            index += 1;
+        }
+        index
+    }
+
+    /// The end of the run of characters of the same vi class as the one under the cursor,
+    /// which is what `cw` changes.
+    fn run_end(&self) -> usize {
+        let chars = self.buffer.chars().collect::<Vec<char>>();
+        let mut index = self.cursor_index;
+        if let Some(class) = chars.get(index).map(|c| vi_class(*c)) {
+            while index < chars.len() && vi_class(chars[index]) == class {
+                index += 1;
+            }
+        }
+        index
+    }
+
+    /// The byte offset of the character at `index`, the cursor counts characters.
+    fn byte_index(&self, index: usize) -> usize {
+        self.buffer.char_indices()
+            .nth(index)
+            .map(|(byte_index, _)| byte_index)
+            .unwrap_or(self.buffer.len())
+    }
+
+    /// Removes the characters from `start` to `end` into the kill ring, leaving the cursor at `start`.
+    fn kill(&mut self, start: usize, end: usize) {
+        if start >= end {
+            return;
+        }
+        let range = self.byte_index(start)..self.byte_index(end);
+        self.kill_ring.push(self.buffer[range.clone()].to_string());
+        if self.kill_ring.len() > KILL_RING_SIZE {
+            self.kill_ring.remove(0);
+        }
+        self.buffer.replace_range(range, "");
+        self.cursor_index = start;
+    }
+
+    fn word_start(&self, is_word: fn(char) -> bool) -> usize {
+        let chars = self.buffer.chars().collect::<Vec<char>>();
+        let mut index = self.cursor_index;
+        while index > 0 && !is_word(chars[index - 1]) {
+            index -= 1;
+        }
+        while index > 0 && is_word(chars[index - 1]) {
+            index -= 1;
+        }
+        index
+    }
+
+    fn word_end(&self) -> usize {
+        let chars = self.buffer.chars().collect::<Vec<char>>();
+        let mut index = self.cursor_index;
+        while index < chars.len() && !is_word_char(chars[index]) {
+            index += 1;
+        }
+        while index < chars.len() && is_word_char(chars[index]) {
+            index += 1;
+        }
+        index
+    }
 }
 
-pub fn main() {
-    for _ in 0..5 {
-        println!();
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// vi splits words into runs of word characters and runs of other characters, spaces separate them.
+fn vi_class(c: char) -> u8 {
+    match c {
+        c if c.is_whitespace() => 0,
+        c if is_word_char(c) => 1,
+        _ => 2,
     }
+}
+
+/// The modes of vi editing, the editor starts in insert mode.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ViMode {
+    #[default]
+    Insert,
+    Normal,
+}
 
-    let input = prompt("***$ ");
-    println!("{input}");
+/// A change made in normal mode, kept so `.` can make it again. Changes that go into insert mode
+/// carry the text typed before going back to normal mode.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ViChange {
+    DeleteChar,
+    DeleteWord,
+    DeleteLine,
+    ChangeWord(String),
+    // `i`, `a`, `I` or `A` and the text inserted
+    Insert(char, String),
+}
+
+/// State of vi editing for one prompt.
+#[derive(Default)]
+pub struct Vi {
+    mode: ViMode,
+    // `d` or `c` waiting for the motion it applies to
+    pending: Option<char>,
+    last_change: Option<ViChange>,
+    // The change that went into insert mode, finished with the text typed since when leaving it
+    insert_change: Option<ViChange>,
+    inserted: String,
+}
+
+impl Vi {
+    pub fn mode(&self) -> ViMode {
+        self.mode
+    }
+
+    /// Records a character typed in insert mode.
+    pub fn typed(&mut self, c: char) {
+        self.inserted.push(c);
+    }
+
+    /// Records a character erased in insert mode.
+    pub fn erased(&mut self) {
+        self.inserted.pop();
+    }
+
+    /// Goes to normal mode, the cursor moves back onto the last character typed as in vi.
+    pub fn escape(&mut self, line: &mut LineBuffer) {
+        if self.mode == ViMode::Normal {
+            self.pending = None;
+            return;
+        }
+        let inserted = std::mem::take(&mut self.inserted);
+        self.last_change = match self.insert_change.take() {
+            Some(ViChange::ChangeWord(_)) => Some(ViChange::ChangeWord(inserted)),
+            Some(ViChange::Insert(kind, _)) => Some(ViChange::Insert(kind, inserted)),
+            _ => self.last_change.take(),
+        };
+        self.mode = ViMode::Normal;
+        line.left();
+    }
+
+    /// Runs a normal mode command. History commands need the prompt, so they are returned as actions.
+    pub fn command(&mut self, line: &mut LineBuffer, c: char) -> Option<Action> {
+        if let Some(operator) = self.pending.take() {
+            match (operator, c) {
+                ('d', 'w') => self.change(line, ViChange::DeleteWord, false),
+                ('d', 'd') => self.change(line, ViChange::DeleteLine, false),
+                ('c', 'w') => self.change(line, ViChange::ChangeWord(String::new()), false),
+                _ => {}
+            }
+            return None;
+        }
+        match c {
+            'h' => line.left(),
+            'l' if line.cursor_index + 1 < line.width() => line.right(),
+            'w' => line.cursor_index = line.vi_word_forward(),
+            'b' => line.cursor_index = line.vi_word_back(),
+            'e' => line.cursor_index = line.vi_word_end(),
+            '0' => line.home(),
+            '$' => line.end(),
+            'x' => self.change(line, ViChange::DeleteChar, false),
+            'd' | 'c' => self.pending = Some(c),
+            'i' | 'a' | 'I' | 'A' => self.change(line, ViChange::Insert(c, String::new()), false),
+            'u' => line.undo(),
+            '.' => if let Some(change) = self.last_change.clone() {
+                self.change(line, change, true);
+            },
+            'k' => return Some(Action::PreviousHistory),
+            'j' => return Some(Action::NextHistory),
+            _ => {}
+        }
+        if self.mode == ViMode::Normal {
+            line.clamp_to_last();
+        }
+        None
+    }
+
+    /// Makes a change, `repeat` replays the text of a change that went into insert mode.
+    fn change(&mut self, line: &mut LineBuffer, change: ViChange, repeat: bool) {
+        line.checkpoint();
+        match change {
+            ViChange::DeleteChar => line.delete(),
+            ViChange::DeleteWord => line.kill(line.cursor_index, line.vi_word_forward()),
+            ViChange::DeleteLine => line.kill(0, line.width()),
+            ViChange::ChangeWord(ref text) => {
+                line.kill(line.cursor_index, line.run_end());
+                self.insert(line, &change, text, repeat);
+            },
+            ViChange::Insert(kind, ref text) => {
+                match kind {
+                    'a' => line.right(),
+                    'I' => line.home(),
+                    'A' => line.end(),
+                    _ => {},
+                }
+                self.insert(line, &change, text, repeat);
+            },
+        }
+        if self.mode == ViMode::Normal {
+            line.clamp_to_last();
+            if !matches!(change, ViChange::ChangeWord(_) | ViChange::Insert(..)) {
+                self.last_change = Some(change);
+            }
+        }
+    }
+
+    fn insert(&mut self, line: &mut LineBuffer, change: &ViChange, text: &str, repeat: bool) {
+        if repeat {
+            line.insert_str(text);
+            line.left();
+            self.last_change = Some(change.clone());
+        } else {
+            self.mode = ViMode::Insert;
+            self.insert_change = Some(change.clone());
+            self.inserted.clear();
+        }
+    }
+}
+
+
+/// Sets the key map used by all prompts, only the first call has an effect.
+pub fn set_keymap(keymap: KeyMap) {
+    let _ = KEYMAP.set(keymap);
 }
 
 pub fn prompt(prefix: &str) -> String {
+    prompt_with(prefix, "", None)
+}
+
+/// Prompts with `initial` already in the buffer so it can be edited, colored by `highlighter`.
+pub fn prompt_with(prefix: &str, initial: &str, highlighter: Option<Highlighter>) -> String {
     let mut line_buffer = LineBuffer::default();
-    let (terminal_width, terminal_height) = terminal::size().unwrap();
-    let mut ctx = Context {
-        stdout: stdout(),
-        prompt_width: prefix.chars().count(),
-        terminal_width: terminal_width as usize,
-        terminal_height: terminal_height as usize,
-        scroll: ScrollState::Unscrolled {
-            y_origin: cursor::position().unwrap().1 as usize,
-        },
-    };
+    let mut ctx = Context::new(vec![(Color::Reset, prefix.to_string())], highlighter, Recall::default());
 
     terminal::enable_raw_mode().unwrap();
-    execute!(ctx.stdout, Print(prefix)).unwrap();
-    loop {
-        if handle(&mut ctx, &mut line_buffer, event::read().unwrap()) {
-            terminal::disable_raw_mode().unwrap();
-            execute!(ctx.stdout, Print("\n")).unwrap();
-            return line_buffer.buffer;
-        }
+    print_prompt(&mut ctx);
+    if !initial.is_empty() {
+        line_buffer.insert_str(initial);
+        update_screen(&mut ctx, &line_buffer, true);
     }
+    // Ending the input answers with an empty line
+    read_line(&mut ctx, line_buffer).unwrap_or_default()
 }
 
-pub fn pretty_prompt(username:&str, seperator:&str ,working_dir:&str, end:&str) -> String{
-    let combined_line = format!("{}{}{}{}", username, seperator, working_dir, end);
-    let mut line_buffer = LineBuffer::default();
-    let (terminal_width, terminal_height) = terminal::size().unwrap();
-    let mut ctx = Context {
-        stdout: stdout(),
-        prompt_width: combined_line.chars().count(),
-        terminal_width: terminal_width as usize,
-        terminal_height: terminal_height as usize,
-        scroll: ScrollState::Unscrolled {
-            y_origin: cursor::position().unwrap().1 as usize,
-        },
-    };
+/// The main prompt, Up and Down go through `history` and Ctrl-R searches it.
+/// With the vi key map the prompt starts with the editing mode.
+/// Returns `None` when the input is ended with Ctrl-D on an empty line.
+pub fn pretty_prompt(username:&str, seperator:&str ,working_dir:&str, end:&str, history: &History) -> Option<String>{
+    let prompt = vec![
+        (Color::Green, username.to_string()),
+        (Color::Reset, seperator.to_string()),
+        (Color::Blue, working_dir.to_string()),
+        (Color::Reset, end.to_string()),
+    ];
+    let mut ctx = Context::new(prompt, None, Recall::new(history));
 
     terminal::enable_raw_mode().unwrap();
-    execute!(
-        ctx.stdout, 
-        SetForegroundColor(Color::Green),
-        Print(username),
-        SetForegroundColor(Color::Reset),
-        Print(seperator),
-        SetForegroundColor(Color::Blue),
-        Print(working_dir),
-        SetForegroundColor(Color::Reset),
-        Print(end)
-        
-    ).unwrap();
+    print_prompt(&mut ctx);
+    read_line(&mut ctx, LineBuffer::default())
+}
+
+/// Edits a line until it is accepted, `None` when the input was ended instead.
+fn read_line(ctx: &mut Context, mut line_buffer: LineBuffer) -> Option<String> {
     loop {
-        if handle(&mut ctx, &mut line_buffer, event::read().unwrap()) {
+        if handle(ctx, &mut line_buffer, event::read().unwrap()) {
             terminal::disable_raw_mode().unwrap();
             execute!(ctx.stdout, Print("\n")).unwrap();
-            return line_buffer.buffer;
+            return match ctx.end_of_input {
+                true => None,
+                false => Some(line_buffer.buffer),
+            };
         }
     }
 }
 
 pub fn handle(ctx: &mut Context, line: &mut LineBuffer, event: Event) -> bool {
+    if let (Some(_), Event::Key(key_event)) = (&ctx.search, &event) {
+        return handle_search(ctx, line, *key_event);
+    }
     match event {
         Event::Key(key_event) => {
-            if key_event.modifiers == KeyModifiers::NONE {
-                match key_event.code {
-                    KeyCode::Char(c) => {
-                        line.insert(c);
-                        update_screen(ctx, line, true);
-                    }
-                    KeyCode::Backspace => {
-                        line.backspace();
-                        update_screen(ctx, line, false);
-                    }
-                    KeyCode::Delete => {
-                        line.delete();
-                        update_screen(ctx, line, false);
-                    }
-                    KeyCode::Left => {
-                        line.left();
-                        update_cursor(ctx, line);
-                    }
-                    KeyCode::Right => {
-                        line.right();
-                        update_cursor(ctx, line);
-                    }
-                    KeyCode::Enter => {
-                        return true;
-                    }
-                    _ => {}
-                }
-            } else if key_event.modifiers == KeyModifiers::SHIFT {
-                match key_event.code {
-                    KeyCode::Char(c) => {
-                        line.insert(c);
-                        update_screen(ctx, line, true);
-                    }
-                    KeyCode::Right => {
-                        line.insert_str("DEBUG ");
-                        update_screen(ctx, line, true);
-                    }
-                    _ => exit(1, "UNSUPPORTED KEY COMBINATION"),
+            let typed = match (key_event.modifiers, key_event.code) {
+                (KeyModifiers::NONE | KeyModifiers::SHIFT, KeyCode::Char(c)) => Some(c),
+                _ => None,
+            };
+            if let (Some(vi), Some(c)) = (ctx.vi.as_mut(), typed) {
+                if vi.mode() == ViMode::Normal {
+                    let action = vi.command(line, c);
+                    redraw_line(ctx, line);
+                    return match action {
+                        Some(action) => perform(ctx, line, action),
+                        None => false,
+                    };
                 }
-            } else {
-                #[allow(clippy::match_single_binding)]
-                match (key_event.modifiers, key_event.code) {
-                    _ => exit(1, "UNSUPPORTED KEY COMBINATION"),
+            }
+            if let Some(action) = ctx.keymap.get(key_event.modifiers, key_event.code) {
+                return perform(ctx, line, action);
+            }
+            // Unbound characters are typed, other combinations do nothing rather than end the program
+            if let Some(c) = typed {
+                line.yanked = None;
+                line.insert(c);
+                if let Some(vi) = ctx.vi.as_mut() {
+                    vi.typed(c);
                 }
+                update_screen(ctx, line, true);
             }
         }
         Event::Mouse(_) => exit(1, "MOUSE CAPTURE SHOULD BE DISABLED"),
-        // Need to reflow the text most likely
-        Event::Resize(_, _) => exit(1, "RESIZING IS NOT SUPPORTED YET"),
+        Event::Resize(width, height) => {
+            // The event can be behind when the window is still being resized
+            let (width, height) = terminal::size().unwrap_or((width, height));
+            ctx.terminal_width = width as usize;
+            ctx.terminal_height = height as usize;
+            // Where the terminal moved the wrapped line to is not known, so it is drawn again from the top
+            queue!(ctx.stdout, terminal::Clear(terminal::ClearType::All), cursor::MoveTo(0, 0)).unwrap();
+            ctx.scroll = ScrollState::Unscrolled { y_origin: 0 };
+            print_prompt(ctx);
+            update_screen(ctx, line, true);
+        }
         Event::FocusGained => (),
         Event::FocusLost => (),
-        Event::Paste(_) => exit(1, "BRACKETED PASTE SHOULD BE DISABLED"),
+        Event::Paste(text) => {
+            // The line is a single line, pasted line breaks become spaces
+            line.yanked = None;
+            line.insert_str(&text.replace("\r\n", " ").replace(['\r', '\n'], " "));
+            update_screen(ctx, line, true);
+        }
     }
 
     false
 }
 
+/// Does what a bound key asks for, returns true when the line is finished.
+fn perform(ctx: &mut Context, line: &mut LineBuffer, action: Action) -> bool {
+    // A yank-pop only follows a yank
+    let yanked = line.yanked.take();
+    match action {
+        Action::AcceptLine => return true,
+        // Abandons the line, like a shell does
+        Action::AbandonLine => {
+            execute!(ctx.stdout, Print("^C")).unwrap();
+            line.replace("");
+            return true;
+        }
+        Action::Abort => {}
+        Action::BackwardChar => {
+            line.left();
+            update_screen(ctx, line, true);
+        }
+        Action::ForwardChar => {
+            line.right();
+            update_screen(ctx, line, true);
+        }
+        Action::BeginningOfLine => {
+            line.home();
+            update_screen(ctx, line, true);
+        }
+        Action::EndOfLine => {
+            line.end();
+            update_screen(ctx, line, true);
+        }
+        Action::BackwardWord => {
+            line.word_left();
+            update_screen(ctx, line, true);
+        }
+        Action::ForwardWord => {
+            line.word_right();
+            update_screen(ctx, line, true);
+        }
+        Action::BackwardDeleteChar => {
+            if let Some(vi) = ctx.vi.as_mut() {
+                vi.erased();
+            }
+            line.backspace();
+            update_screen(ctx, line, false);
+        }
+        Action::EndOfFile if line.buffer.is_empty() => {
+            ctx.end_of_input = true;
+            return true;
+        }
+        Action::DeleteChar | Action::EndOfFile => {
+            line.delete();
+            update_screen(ctx, line, false);
+        }
+        Action::KillLine => {
+            line.kill_to_end();
+            update_screen(ctx, line, false);
+        }
+        Action::UnixLineDiscard => {
+            line.kill_to_start();
+            update_screen(ctx, line, false);
+        }
+        Action::UnixWordRubout => {
+            line.kill_word_left();
+            update_screen(ctx, line, false);
+        }
+        Action::BackwardKillWord => {
+            line.kill_word_back();
+            update_screen(ctx, line, false);
+        }
+        Action::KillWord => {
+            line.kill_word_right();
+            update_screen(ctx, line, false);
+        }
+        Action::Yank => {
+            line.yank();
+            update_screen(ctx, line, true);
+        }
+        Action::YankPop => {
+            line.yanked = yanked;
+            line.yank_pop();
+            update_screen(ctx, line, true);
+        }
+        Action::TransposeChars => {
+            line.transpose();
+            update_screen(ctx, line, false);
+        }
+        Action::PreviousHistory => {
+            if let Some(entry) = ctx.recall.older(&line.buffer).map(str::to_string) {
+                line.replace(&entry);
+                update_screen(ctx, line, true);
+            }
+        }
+        Action::NextHistory => {
+            if let Some(entry) = ctx.recall.newer().map(str::to_string) {
+                line.replace(&entry);
+                update_screen(ctx, line, true);
+            }
+        }
+        Action::ViMovementMode => {
+            if let Some(vi) = ctx.vi.as_mut() {
+                vi.escape(line);
+                redraw_line(ctx, line);
+            }
+        }
+        Action::ReverseSearchHistory => {
+            ctx.search = Some(Search {
+                query: String::new(),
+                matched: None,
+                failed: false,
+                original: line.buffer.clone(),
+            });
+            redraw_line(ctx, line);
+        }
+    }
+    false
+}
+
+/// Keys typed during a Ctrl-R search. Typing narrows the search, the search key moves on to older
+/// matches, Enter runs the line found, Ctrl-G or Ctrl-C put back the line from before the search,
+/// and any other key ends the search leaving the line found to be edited.
+fn handle_search(ctx: &mut Context, line: &mut LineBuffer, key_event: KeyEvent) -> bool {
+    let mut search = match ctx.search.take() {
+        Some(search) => search,
+        None => return false,
+    };
+    let action = ctx.keymap.get(key_event.modifiers, key_event.code);
+    match (action, key_event.modifiers, key_event.code) {
+        (Some(Action::ReverseSearchHistory), _, _) => {
+            let before = search.matched.unwrap_or(ctx.recall.entries.len());
+            search_history(ctx, line, &mut search, before);
+        }
+        (Some(Action::Abort | Action::AbandonLine), _, _) => {
+            line.replace(&search.original);
+            redraw_line(ctx, line);
+            return false;
+        }
+        (Some(Action::BackwardDeleteChar), _, _) => {
+            search.query.pop();
+            let before = ctx.recall.entries.len();
+            search_history(ctx, line, &mut search, before);
+        }
+        (Some(Action::AcceptLine), _, _) => {
+            redraw_line(ctx, line);
+            return true;
+        }
+        (None, KeyModifiers::NONE | KeyModifiers::SHIFT, KeyCode::Char(c)) => {
+            search.query.push(c);
+            // The line shown stays if it still matches
+            let before = search.matched.map(|index| index + 1).unwrap_or(ctx.recall.entries.len());
+            search_history(ctx, line, &mut search, before);
+        }
+        _ => {
+            redraw_line(ctx, line);
+            return false;
+        }
+    }
+    ctx.search = Some(search);
+    redraw_line(ctx, line);
+    false
+}
+
+fn search_history(ctx: &Context, line: &mut LineBuffer, search: &mut Search, before: usize) {
+    if search.query.is_empty() {
+        search.matched = None;
+        search.failed = false;
+        line.replace(&search.original);
+        return;
+    }
+    match ctx.recall.search(&search.query, before) {
+        Some((index, offset)) => {
+            search.matched = Some(index);
+            search.failed = false;
+            line.replace(&ctx.recall.entries[index]);
+            line.cursor_index = offset;
+        }
+        // The last match stays on screen, as in readline
+        None => search.failed = true,
+    }
+}
+
+/// Prints the prompt where the cursor is, when the line editor starts.
+pub fn print_prompt(ctx: &mut Context) {
+    for (color, text) in ctx.prompt_spans() {
+        queue!(ctx.stdout, SetForegroundColor(color), Print(text)).unwrap();
+    }
+    execute!(ctx.stdout, SetForegroundColor(Color::Reset)).unwrap();
+}
+
+/// Redraws the prompt and the line buffer, used when the prompt changes to or from the search prompt.
+pub fn redraw_line(ctx: &mut Context, line: &LineBuffer) {
+    ctx.prompt_width = ctx.prompt_spans().iter().map(|(_, text)| text.chars().count()).sum();
+    if let Some(y) = prompt_start_y_coord(ctx) {
+        queue!(
+            ctx.stdout,
+            cursor::MoveTo(0, y),
+            terminal::Clear(terminal::ClearType::FromCursorDown),
+        )
+        .unwrap();
+        print_prompt(ctx);
+    }
+    update_screen(ctx, line, true);
+}
+
 /// Updates the frame by (optionally) scrolling, updating the cursor, and redrawing the line buffer.
 pub fn update_screen(ctx: &mut Context, line: &LineBuffer, scroll: bool) {
     if scroll {
@@ -246,16 +1038,23 @@ pub fn update_screen(ctx: &mut Context, line: &LineBuffer, scroll: bool) {
 
 pub fn redraw_buffer(ctx: &mut Context, line: &LineBuffer) {
     let (draw_start_x, draw_start_y) = prompt_end_coord(ctx);
-    let scroll = ctx.scroll.clone();
-    let terminal_width = ctx.terminal_width;
     execute!(ctx.stdout, cursor::SavePosition).unwrap();
     queue!(
         ctx.stdout,
         terminal::Clear(terminal::ClearType::FromCursorDown),
         cursor::MoveTo(draw_start_x, draw_start_y),
-        Print(line.segment(scroll, terminal_width))
     )
     .unwrap();
+    let segment = line.segment(ctx);
+    match ctx.highlighter {
+        Some(highlighter) => {
+            for (color, text) in highlighter(segment) {
+                queue!(ctx.stdout, SetForegroundColor(color), Print(text)).unwrap();
+            }
+            queue!(ctx.stdout, SetForegroundColor(Color::Reset)).unwrap();
+        }
+        None => queue!(ctx.stdout, Print(segment)).unwrap(),
+    }
     execute!(ctx.stdout, cursor::RestorePosition).unwrap();
 }
 
@@ -267,7 +1066,7 @@ pub fn update_cursor(ctx: &mut Context, line: &LineBuffer) {
 }
 
 /// Scrolls the terminal down the necessary amount of lines, changing the scroll state as needed.
-/// This should generally be called first in the event that any text is added to the buffer.
+/// This should generally be called first in the event that any text is added to or removed from the buffer.
 pub fn update_scroll(ctx: &mut Context, line: &LineBuffer) {
     // Check if scroll is required, and if it is, scroll as needed and update the scroll state
     match ctx.scroll {
@@ -312,20 +1111,38 @@ pub fn update_scroll(ctx: &mut Context, line: &LineBuffer) {
             }
         }
         ScrollState::ScrolledPastPrompt { scroll } => {
-            let mut lines_on_screen = line.height(ctx) - scroll;
-            if lines_on_screen > ctx.terminal_height {
-                let overrun = lines_on_screen - ctx.terminal_height;
+            // If the editor has been scrolled past the prompt, the line takes up the entire terminal
+            // height and the screen follows the cursor: past the bottom the terminal scrolls down
+            let lines = line.height(ctx);
+            let row = true_index(ctx, line) / ctx.terminal_width;
+            if row >= scroll + ctx.terminal_height {
+                let overrun = row + 1 - scroll - ctx.terminal_height;
                 scroll_down(ctx, overrun);
 
-                lines_on_screen -= overrun;
                 ctx.scroll = ScrollState::ScrolledPastPrompt {
                     scroll: scroll + overrun,
                 };
+            } else if row < scroll || lines < scroll + ctx.terminal_height {
+                // The cursor moved above the screen, or the line got shorter, replaced by a history
+                // entry or cut down: the lines scrolled off the top come back as far as the cursor
+                // needs, and once the whole line fits so does the prompt
+                queue!(ctx.stdout, terminal::Clear(terminal::ClearType::All)).unwrap();
+                ctx.scroll = match lines > ctx.terminal_height {
+                    true => {
+                        let scroll = (lines - ctx.terminal_height).min(row);
+                        if scroll == 0 {
+                            queue!(ctx.stdout, cursor::MoveTo(0, 0)).unwrap();
+                            print_prompt(ctx);
+                        }
+                        ScrollState::ScrolledPastPrompt { scroll }
+                    },
+                    false => {
+                        queue!(ctx.stdout, cursor::MoveTo(0, 0)).unwrap();
+                        print_prompt(ctx);
+                        ScrollState::Unscrolled { y_origin: 0 }
+                    }
+                };
             }
-
-            // If the editor has been scrolled past the prompt, the last line should always be at
-            // the bottom, so the on-screen portion will always take up the entire terminal height
-            assert!(lines_on_screen == ctx.terminal_height);
         }
     }
 }
@@ -383,9 +1200,23 @@ pub fn cursor_y_coord(ctx: &Context, line: &LineBuffer) -> u16 {
     y as u16
 }
 
+/// Calculates the y-coordinate of the start of the prompt, `None` once it has scrolled off the screen.
+pub fn prompt_start_y_coord(ctx: &Context) -> Option<u16> {
+    match ctx.scroll {
+        ScrollState::Unscrolled { y_origin } => Some(y_origin as u16),
+        ScrollState::Scrolled { y_origin, scroll } => Some((y_origin - scroll) as u16),
+        ScrollState::ScrolledPastPrompt { scroll: 0 } => Some(0),
+        ScrollState::ScrolledPastPrompt { scroll: _ } => None,
+    }
+}
+
 /// Calculates the x-coordinate of the end of the prompt, used for redrawing the buffer.
 pub fn prompt_end_x_coord(ctx: &Context) -> u16 {
-    let x = ctx.prompt_width % ctx.terminal_width;
+    let x = match ctx.scroll {
+        // Rows of the prompt above the screen are not drawn, the buffer starts in the corner
+        ScrollState::ScrolledPastPrompt { scroll } => (scroll * ctx.terminal_width).max(ctx.prompt_width) % ctx.terminal_width,
+        _ => ctx.prompt_width % ctx.terminal_width,
+    };
     assert!(x < ctx.terminal_width);
     x as u16
 }
@@ -396,7 +1227,7 @@ pub fn prompt_end_y_coord(ctx: &Context) -> u16 {
     let y = match ctx.scroll {
         ScrollState::Unscrolled { y_origin } => base + y_origin,
         ScrollState::Scrolled { y_origin, scroll } => base + (y_origin - scroll),
-        ScrollState::ScrolledPastPrompt { scroll: _ } => 0,
+        ScrollState::ScrolledPastPrompt { scroll } => (scroll * ctx.terminal_width).max(ctx.prompt_width) / ctx.terminal_width - scroll,
     };
 
     assert!(y < ctx.terminal_height);
@@ -410,9 +1241,327 @@ pub fn exit(code: i32, msg: &str) -> ! {
 }
 
 pub fn ciel_div(a: usize, b: usize) -> usize {
-    let mut result = a / b;
-    if a % b > 0 {
-        result += 1;
+    a.div_ceil(b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(text: &str) -> LineBuffer {
+        let mut line = LineBuffer::default();
+        line.insert_str(text);
+        line
+    }
+
+    #[test]
+    fn word_motion_and_transpose() {
+        let mut line = buffer("SELECT name, total FROM orders");
+        line.word_left();
+        assert_eq!(line.cursor_index, 24);
+        line.word_left();
+        line.word_left();
+        assert_eq!(line.cursor_index, 13);
+        line.word_right();
+        assert_eq!(line.cursor_index, 18);
+        line.home();
+        line.word_right();
+        assert_eq!(line.cursor_index, 6);
+
+        let mut line = buffer("selcet");
+        line.left();
+        line.left();
+        line.transpose();
+        assert_eq!(line.buffer, "select");
+        line.end();
+        line.transpose();
+        assert_eq!(line.buffer, "seletc");
+    }
+
+    #[test]
+    fn kills_can_be_yanked_back() {
+        let mut line = buffer("data how many users");
+        line.kill_word_left();
+        line.kill_word_left();
+        assert_eq!(line.buffer, "data how ");
+        line.home();
+        line.kill_to_end();
+        assert_eq!(line.buffer, "");
+
+        line.yank();
+        assert_eq!(line.buffer, "data how ");
+        line.yank_pop();
+        assert_eq!(line.buffer, "many ");
+        line.yank_pop();
+        assert_eq!(line.buffer, "users");
+        line.home();
+        line.kill_word_right();
+        assert_eq!(line.buffer, "");
+    }
+
+    #[test]
+    fn edits_count_characters_not_bytes() {
+        let mut line = buffer("café");
+        line.insert('s');
+        line.left();
+        line.left();
+        line.backspace();
+        line.delete();
+        assert_eq!(line.buffer, "cas");
+        line.end();
+        line.delete();
+        line.kill_word_back();
+        line.insert_str("naïve_été");
+        line.kill_word_back();
+        assert_eq!(line.buffer, "");
+    }
+
+    // A context for a terminal of the given size, without asking a terminal for it or drawing on one
+    fn context(terminal_width: usize, terminal_height: usize, scroll: ScrollState, history: &History) -> Context {
+        Context {
+            stdout: Box::new(Vec::new()),
+            prompt: vec![(Color::Reset, "> ".to_string())],
+            highlighter: None,
+            keymap: KEYMAP.get_or_init(KeyMap::default),
+            recall: Recall::new(history),
+            search: None,
+            end_of_input: false,
+            vi: None,
+            prompt_width: 2,
+            terminal_width,
+            terminal_height,
+            scroll,
+        }
+    }
+
+    fn key(code: KeyCode) -> Event {
+        Event::Key(KeyEvent::new(code, KeyModifiers::NONE))
+    }
+
+    #[test]
+    fn recalling_a_shorter_line_scrolls_back() {
+        let mut history = History::new(10);
+        history.add(&"x".repeat(50)).unwrap();
+        history.add("short").unwrap();
+        // 10 columns and 4 rows, a line of 2 + 97 characters is 10 rows long, 6 of them scrolled off
+        let mut ctx = context(10, 4, ScrollState::ScrolledPastPrompt { scroll: 6 }, &history);
+        let mut line = buffer(&"y".repeat(97));
+
+        handle(&mut ctx, &mut line, key(KeyCode::Up));
+        assert_eq!(line.buffer, "short");
+        assert_eq!(ctx.scroll, ScrollState::Unscrolled { y_origin: 0 });
+
+        ctx.scroll = ScrollState::ScrolledPastPrompt { scroll: 6 };
+        line.replace(&"y".repeat(97));
+        handle(&mut ctx, &mut line, key(KeyCode::Up));
+        assert_eq!(line.buffer, "x".repeat(50));
+        // 6 rows, the last 4 on screen
+        assert_eq!(ctx.scroll, ScrollState::ScrolledPastPrompt { scroll: 2 });
+    }
+
+    #[test]
+    fn cutting_a_long_line_scrolls_back() {
+        let mut history = History::new(10);
+        history.add("short").unwrap();
+        let mut vi = Vi::default();
+        let mut line = buffer(&"y".repeat(97));
+        vi.escape(&mut line);
+        let mut ctx = context(10, 4, ScrollState::Unscrolled { y_origin: 0 }, &history);
+        ctx.vi = Some(vi);
+        ctx.prompt_width = ctx.prompt_spans().iter().map(|(_, text)| text.chars().count()).sum();
+        ctx.scroll = ScrollState::ScrolledPastPrompt { scroll: line.height(&ctx) - 4 };
+        handle(&mut ctx, &mut line, key(KeyCode::Char('d')));
+        handle(&mut ctx, &mut line, key(KeyCode::Char('d')));
+        assert_eq!(line.buffer, "");
+        assert_eq!(ctx.scroll, ScrollState::Unscrolled { y_origin: 0 });
+
+        // A search match can be shorter than the line it replaces
+        ctx.vi = None;
+        line.replace(&"y".repeat(97));
+        ctx.search = Some(Search { query: String::new(), matched: None, failed: false, original: line.buffer.clone() });
+        ctx.prompt_width = ctx.prompt_spans().iter().map(|(_, text)| text.chars().count()).sum();
+        ctx.scroll = ScrollState::ScrolledPastPrompt { scroll: line.height(&ctx) - 4 };
+        handle(&mut ctx, &mut line, key(KeyCode::Char('s')));
+        assert_eq!(line.buffer, "short");
+        assert_eq!(ctx.scroll, ScrollState::Unscrolled { y_origin: 0 });
+    }
+
+    #[test]
+    fn moving_above_the_screen_scrolls_back() {
+        let history = History::new(10);
+        let mut line = buffer(&format!("{}ends", "word ".repeat(19)));
+        let mut ctx = context(10, 4, ScrollState::Unscrolled { y_origin: 0 }, &history);
+        ctx.scroll = ScrollState::ScrolledPastPrompt { scroll: line.height(&ctx) - 4 };
+
+        // The last word starts on screen
+        handle(&mut ctx, &mut line, Event::Key(KeyEvent::new(KeyCode::Left, KeyModifiers::CONTROL)));
+        assert_eq!(ctx.scroll, ScrollState::ScrolledPastPrompt { scroll: 7 });
+        handle(&mut ctx, &mut line, key(KeyCode::Home));
+        assert_eq!(line.cursor_index, 0);
+        assert_eq!(ctx.scroll, ScrollState::ScrolledPastPrompt { scroll: 0 });
+        assert_eq!(cursor_coord(&ctx, &line), (2, 0));
+        // Only the rows on screen are drawn, the rest of the line waits below
+        assert_eq!(line.segment(&ctx).chars().count(), 4 * 10 - 2);
+        handle(&mut ctx, &mut line, key(KeyCode::Right));
+        assert_eq!(cursor_coord(&ctx, &line), (3, 0));
+
+        let mut vi = Vi::default();
+        vi.escape(&mut line);
+        line.end();
+        ctx.vi = Some(vi);
+        ctx.prompt_width = ctx.prompt_spans().iter().map(|(_, text)| text.chars().count()).sum();
+        ctx.scroll = ScrollState::ScrolledPastPrompt { scroll: line.height(&ctx) - 4 };
+        handle(&mut ctx, &mut line, key(KeyCode::Char('0')));
+        assert_eq!(ctx.scroll, ScrollState::ScrolledPastPrompt { scroll: 0 });
+        assert_eq!(cursor_coord(&ctx, &line), (8, 0));
+    }
+
+    #[test]
+    fn ctrl_d_ends_the_input_on_an_empty_line() {
+        let history = History::new(10);
+        let mut ctx = context(10, 4, ScrollState::Unscrolled { y_origin: 0 }, &history);
+        let ctrl_d = Event::Key(KeyEvent::new(KeyCode::Char('d'), KeyModifiers::CONTROL));
+        let mut line = buffer("ab");
+        line.home();
+
+        assert!(!handle(&mut ctx, &mut line, ctrl_d.clone()));
+        assert_eq!(line.buffer, "b");
+        assert!(!handle(&mut ctx, &mut line, ctrl_d.clone()));
+        assert!(!ctx.end_of_input);
+        assert!(handle(&mut ctx, &mut line, ctrl_d));
+        assert!(ctx.end_of_input);
+    }
+
+    #[test]
+    fn resizing_and_pasting_redraw_the_line() {
+        let history = History::new(10);
+        let mut line = buffer(&"y".repeat(97));
+        let mut ctx = context(10, 4, ScrollState::ScrolledPastPrompt { scroll: 6 }, &history);
+
+        handle(&mut ctx, &mut line, Event::Resize(40, 10));
+        let (width, height) = terminal::size().unwrap_or((40, 10));
+        assert_eq!((ctx.terminal_width, ctx.terminal_height), (width as usize, height as usize));
+        assert!(cursor_y_coord(&ctx, &line) < height);
+
+        line.replace("SELECT");
+        ctx.scroll = ScrollState::Unscrolled { y_origin: 0 };
+        handle(&mut ctx, &mut line, Event::Paste(" *\r\nFROM users".to_string()));
+        assert_eq!(line.buffer, "SELECT * FROM users");
     }
-    result
-}
\ No newline at end of file
+
+    fn keys(vi: &mut Vi, line: &mut LineBuffer, keys: &str) {
+        for c in keys.chars() {
+            match vi.mode() {
+                ViMode::Normal => {
+                    vi.command(line, c);
+                },
+                ViMode::Insert if c == '\x1b' => vi.escape(line),
+                ViMode::Insert => {
+                    line.insert(c);
+                    vi.typed(c);
+                },
+            }
+        }
+    }
+
+    #[test]
+    fn vi_motions() {
+        let mut line = buffer("SELECT count(*) FROM users");
+        let mut vi = Vi::default();
+        vi.escape(&mut line);
+        assert_eq!(line.cursor_index, 25);
+        keys(&mut vi, &mut line, "0w");
+        assert_eq!(line.cursor_index, 7);
+        keys(&mut vi, &mut line, "w");
+        assert_eq!(line.cursor_index, 12);
+        keys(&mut vi, &mut line, "e");
+        assert_eq!(line.cursor_index, 14);
+        keys(&mut vi, &mut line, "bb");
+        assert_eq!(line.cursor_index, 7);
+        keys(&mut vi, &mut line, "$l");
+        assert_eq!(line.cursor_index, 25);
+        keys(&mut vi, &mut line, "hhh");
+        assert_eq!(line.cursor_index, 22);
+    }
+
+    #[test]
+    fn vi_changes_undo_and_repeat() {
+        let mut line = buffer("data how many users");
+        let mut vi = Vi::default();
+        keys(&mut vi, &mut line, "\x1b0dw");
+        assert_eq!(line.buffer, "how many users");
+        keys(&mut vi, &mut line, ".");
+        assert_eq!(line.buffer, "many users");
+        keys(&mut vi, &mut line, "uu");
+        assert_eq!(line.buffer, "data how many users");
+
+        keys(&mut vi, &mut line, "wwcwtotal\x1b");
+        assert_eq!(line.buffer, "data how total users");
+        assert_eq!(vi.mode(), ViMode::Normal);
+        keys(&mut vi, &mut line, "w.");
+        assert_eq!(line.buffer, "data how total total");
+
+        keys(&mut vi, &mut line, "0xx");
+        assert_eq!(line.buffer, "ta how total total");
+        keys(&mut vi, &mut line, "A?\x1bI> \x1b");
+        assert_eq!(line.buffer, "> ta how total total?");
+        // I repeats at the start of the line wherever the cursor is
+        keys(&mut vi, &mut line, "$.");
+        assert_eq!(line.buffer, "> > ta how total total?");
+        keys(&mut vi, &mut line, "dd");
+        assert_eq!(line.buffer, "");
+        keys(&mut vi, &mut line, "u");
+        assert_eq!(line.buffer, "> > ta how total total?");
+    }
+
+    #[test]
+    fn history_keeps_recent_lines_once() {
+        let mut history = History::new(3);
+        for line in ["tables", "data how many users", "  ", "tables", "sql SELECT 1", "er"] {
+            history.add(line).unwrap();
+        }
+        assert_eq!(history.iter().collect::<Vec<&str>>(), vec!["tables", "sql SELECT 1", "er"]);
+    }
+
+    #[test]
+    fn history_is_kept_in_its_file() {
+        let path = std::env::temp_dir().join(format!("data_bot2_history_{}", std::process::id())).join("history");
+        let _ = fs::remove_file(&path);
+        let mut history = History::load(Some(path.clone()), 10).unwrap();
+        history.add("connect sales.db").unwrap();
+        history.add("tables").unwrap();
+
+        let history = History::load(Some(path), 10).unwrap();
+        assert_eq!(history.iter().collect::<Vec<&str>>(), vec!["connect sales.db", "tables"]);
+    }
+
+    #[test]
+    fn recall_returns_to_the_draft() {
+        let mut history = History::new(10);
+        history.add("first").unwrap();
+        history.add("second").unwrap();
+        let mut recall = Recall::new(&history);
+
+        assert_eq!(recall.newer(), None);
+        assert_eq!(recall.older("half typed"), Some("second"));
+        assert_eq!(recall.older("second"), Some("first"));
+        assert_eq!(recall.older("first"), None);
+        assert_eq!(recall.newer(), Some("second"));
+        assert_eq!(recall.newer(), Some("half typed"));
+        assert_eq!(recall.newer(), None);
+    }
+
+    #[test]
+    fn search_finds_older_matches() {
+        let mut history = History::new(10);
+        for line in ["data how many users", "sql SELECT * FROM users", "tables"] {
+            history.add(line).unwrap();
+        }
+        let recall = Recall::new(&history);
+
+        assert_eq!(recall.search("users", 3), Some((1, 18)));
+        assert_eq!(recall.search("users", 1), Some((0, 14)));
+        assert_eq!(recall.search("users", 0), None);
+        assert_eq!(recall.search("orders", 3), None);
+    }
+}
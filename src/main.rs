@@ -1,17 +1,32 @@
 use std::{env, path::PathBuf, process::Command, };
 
-use async_openai::{Client, types::{CreateThreadRequestArgs, CreateAssistantRequestArgs, AssistantTools, AssistantToolsFunction, ChatCompletionFunctions, CreateMessageRequestArgs, CreateRunRequestArgs, RunStatus, MessageContent, StepDetails, RunStepDetailsToolCalls, SubmitToolOutputsRunRequest, ToolsOutputs}, config::OpenAIConfig};
+use async_openai::{Client, config::OpenAIConfig};
 use crossterm::style::Color;
 
-use serde_json::{self, Value};
-
 mod style;
 //use crate::style::*;
 mod ledit;
 //use crate::ledit::*;
 mod sql_ops;
 use sql_ops::DataBase;
-use sqlx::{Pool, Sqlite, sqlite::SqliteRow};
+mod backend;
+use backend::{Backend, AssistantsBackend, ChatBackend, MockBackend};
+mod connections;
+use connections::Connections;
+mod config;
+use config::{BackendKind, Config};
+mod data;
+use data::ToolContext;
+mod schema;
+mod er;
+mod export;
+mod import;
+mod keymap;
+mod table;
+mod tool_output;
+use tool_output::Budget;
+#[cfg(test)]
+mod fixtures;
 
 //fix the thing where it hard quits if you dont have a key
 
@@ -45,9 +60,38 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     style::print(Color::Reset, Color::Reset, "Type ")?;
     style::print(Color::Red, Color::Reset, "exit")?;
     style::print(Color::Reset, Color::Reset, " to exit the program.\n\n")?;
-    let key = match env::var("OPENAI_API_KEY") {
-        Ok(key) => key,
-        Err(_) => {
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(e) => {
+            style::print(Color::Red, Color::Reset, "Error: ")?;
+            style::println(Color::Reset, Color::Reset, format!("{}", e).as_str())?;
+            std::process::exit(1);
+        }
+    };
+
+    ledit::set_keymap(config.keymap.clone());
+
+    match config.backend {
+        BackendKind::Assistants => repl(AssistantsBackend::new(openai_client(&config)?, &config.model), &config).await,
+        BackendKind::Chat => repl(ChatBackend::new(openai_client(&config)?, &config.model), &config).await,
+        BackendKind::Mock => match config.mock_script {
+            Some(ref path) => repl(MockBackend::from_file(path)?, &config).await,
+            None => repl(MockBackend::default(), &config).await,
+        },
+    }
+}
+
+/// Builds an OpenAI client from the config, exiting with a hint if no key is available.
+fn openai_client(config: &Config) -> Result<Client<OpenAIConfig>, Box<dyn std::error::Error>> {
+    let mut openai_config = OpenAIConfig::default();
+    if let Some(ref api_base) = config.api_base {
+        openai_config = openai_config.with_api_base(api_base);
+    }
+    match config.api_key {
+        Some(ref key) => openai_config = openai_config.with_api_key(key),
+        // Local OpenAI-compatible servers usually do not check the key
+        None if config.api_base.is_some() => {},
+        None => {
             style::println(Color::Red, Color::Reset, "Error: OPENAI_API_KEY not found")?;
             style::print(Color::Reset, Color::Reset, "please set your key using ")?;
             style::print(Color::Green, Color::Reset, "OPENAI_API_KEY")?;
@@ -56,127 +100,52 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             std::process::exit(1);
         }
     };
-    let config = OpenAIConfig::default().with_api_key(key);
-    let client = Client::with_config(config);
-    
-    let thread_request = CreateThreadRequestArgs::default()
-        .build()?;
-    let thread = client.threads().create(thread_request).await?;
-    let assistant_request = CreateAssistantRequestArgs::default()
-        .name("Data")
-        .model("gpt-3.5-turbo-16k")
-        .description("An sql assistant that can help explore sqlite databases.")
-        .instructions("
-FOLLOW THESE INSTUCTIONS PRECISELY:
-you are an AI designed to help people explore sqlite databases.
-    you are an expert at sqlite.
-            
-    you will be given a question from the user,
-    you can query the database,
-    you can respond to questions from the user which may not require a database query
-            
-    This assistant can connect to a database, list the tables and columns in the database, and execute queries on the database. 
-    DO NOT summarize or display any data yourself after a database request has been made,
-        ONLY respond with \"Database Queried\".
-    DO NOT create a new message after a function call
-            
-    you have complete access to the database, and can answer any query about it.
-    you have the ability to remember infomration from previous queries
-    you have the ability to recall data you've seen before without querying the database
+    Ok(Client::with_config(openai_config))
+}
 
-    IF the user tells you not to query the database:
-        you are still able to provid information about the database
-        use your ability to recall information to attempt to answer the question
+/// Reads the rest of a statement while its lines end in a backslash.
+fn read_continued(first_line: &str) -> String {
+    let mut lines = Vec::new();
+    let mut line = first_line.to_string();
+    while let Some(start) = line.strip_suffix('\\') {
+        lines.push(start.trim_end().to_string());
+        line = ledit::prompt("    ...> ").trim().to_string();
+    }
+    lines.push(line);
+    lines.into_iter()
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<String>>()
+        .join("\n")
+}
 
-    IF the database queries successfully:
-        ONLY respond with \"Database Queried\"
-            
-    IF the user enters a query whos parameters may be invaild given the database:
-        DO NOT run the query
-        DO explain to the user why the query might be invalid
-            
-    IF you recieve an empty result from a database query:
-        the query is invalid
-        DO NOT attempt any corrections
-        DO explain to the user why the query might be invalid")
-        .tools(vec![
-            AssistantTools::Function(AssistantToolsFunction{
-                r#type: "function".to_string(),
-                function: ChatCompletionFunctions{
-                    name:"db_query".to_string(),
-                    description:Some("generate a sqlite query to retrieve data requested by the user, 
-                        this data will be printed to the user, you do not need to summarize it.".to_string()),
-                    parameters:serde_json::from_str::<Value>("{
-                        \"type\": \"object\", 
-                        \"properties\": {
-                            \"query\": {
-                                \"type\": \"string\",
-                                \"description\": \"the query to be executed\"
-                            }
-                        },
-                        
-                        \"required\": [\"query\"]
-                    }").unwrap(),
-                }
-            }),
-            //hello world
-            AssistantTools::Function(AssistantToolsFunction{
-                r#type: "function".to_string(),
-                function: ChatCompletionFunctions{
-                    name:"hello_world".to_string(),
-                    description:Some("print hello world".to_string()),
-                    parameters:serde_json::from_str::<Value>("{
-                        \"type\": \"object\", 
-                        \"properties\": {
-                            \"name\": {
-                                \"type\": \"string\",
-                                \"description\": \"the name of the person to say hello to\"
-                            }
-                        },
-                        
-                        \"required\": [\"name\"]
-                    }").unwrap(),
-                }
-            }),
-            //connect to database
-            AssistantTools::Function(AssistantToolsFunction{
-                r#type: "function".to_string(),
-                function: ChatCompletionFunctions{
-                    name:"connect_to_database".to_string(),
-                    description:Some("connect to a sqlite database".to_string()),
-                    parameters:serde_json::from_str::<Value>("{
-                        \"type\": \"object\", 
-                        \"properties\": {
-                            \"database\": {
-                                \"type\": \"string\",
-                                \"description\": \"the path to the database to connect to\"
-                            }
-                        },
-                        
-                        \"required\": [\"database\"]
-                    }").unwrap(),
-                }
-            }),
-        ])
-        .build()?;
-    let assistant = client.assistants().create(assistant_request).await?;
-    let mut data_base = Vec::<Pool<Sqlite>>::new();
+async fn repl<B: Backend>(mut backend: B, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+    let budget = Budget::new(&config.model, config.max_rows, config.token_budget, config.schema_budget)?;
+    let mut allow_writes = config.allow_writes;
+    let mut approve_queries = config.approve_queries;
+    let mut share_sql = config.share_sql;
+    backend.create_session().await?;
+    let mut connections = Connections::default();
+    //an empty database in memory, so there is always somewhere to import files and create tables
+    connections.add("memory", "sqlite::memory:", DataBase::create_connection("sqlite::memory:", false).await?)?;
+    let mut last_result = None;
+    let mut history = match ledit::History::load(config.history_file.clone(), config.history_size) {
+        Ok(history) => history,
+        Err(e) => {
+            //keep the history in memory rather than overwrite a file that could not be read
+            style::println(Color::DarkGrey, Color::Reset, format!("could not read the history file: {}", e).as_str())?;
+            ledit::History::new(config.history_size)
+        }
+    };
     
     let mut working_dir:PathBuf = env::current_dir()?;
-    let mut data_base_path = String::new();
     let user_name = whoami::username();
     let os = whoami::platform();
 
     //message to the bot
-    let message = CreateMessageRequestArgs::default()
-        .role("user")
-        .content(format!("Hello, my name is {} and I am using {}.", user_name, os).as_str())
-        .build()?;
-    let _message = client.threads().messages(&thread.id).create(message).await?;
+    backend.send_user_message(format!("Hello, my name is {} and I am using {}.", user_name, os).as_str()).await?;
+    backend.send_user_message("an empty in-memory database is connected as memory, it is the active database until I connect another").await?;
     //message to the bot
-    let message = CreateMessageRequestArgs::default()
-        .role("user")
-        .content("
+    backend.send_user_message("
 FOLLOW THESE INSTUCTIONS:
 once i have queried a database, 
     DO NOT post a message after you have called the query function,
@@ -186,9 +155,7 @@ once i have queried a database,
         DO NOT run a query,
         DO attempt to explain to me why the query may be invalid
     IF the database is queried successfully:
-        ONLY reply with \"query successful\"")
-        .build()?;
-    let _message = client.threads().messages(&thread.id).create(message).await?;
+        ONLY reply with \"query successful\"").await?;
 
     // --------
     // MAIN LOOP
@@ -196,24 +163,34 @@ once i have queried a database,
     loop {
         
         //print the prompt
-        let working_path = if data_base.len() > 0 {
-            data_base_path.clone()
+        let working_path = if let Some(connection) = connections.active() {
+            connection.path.clone()
         }
         else{
             let wd = working_dir.to_str().unwrap().to_string();
             wd
         };
-        let input = ledit::pretty_prompt(&user_name, " @ ",
-            &working_path, " -> ");
+        let input = match ledit::pretty_prompt(&user_name, " @ ", &working_path, " -> ", &history) {
+            Some(input) => input,
+            //ctrl-d on an empty line ends the input, the same as exit
+            None => {
+                backend.close().await?;
+                break
+            },
+        };
 
         let input = input.trim();
+        if let Err(e) = history.add(input) {
+            style::println(Color::DarkGrey, Color::Reset, format!("could not save the history: {}", e).as_str())?;
+        }
 
         let words = input.split(' ').collect::<Vec<&str>>();
 
         match words[0] {
+            //an empty line, or one abandoned with ctrl-c
+            "" => continue,
             "exit" => {
-                client.assistants().delete(&assistant.id).await?;
-                client.threads().delete(&thread.id).await?;
+                backend.close().await?;
                 break
             },
             "clear" => {
@@ -270,7 +247,7 @@ once i have queried a database,
                     words[1..].iter().map(|s| s.to_string()).collect()
                 };
 
-                let _entries = match Command::new("ls")
+                match Command::new("ls")
                     .args(&args)
                     .status(){
                         Ok(_) => {},
@@ -282,10 +259,25 @@ once i have queried a database,
                     
                 //style::println(Color::Blue, Color::Reset, &entries)?;
             },
-            //create a new database connection
+            //create a new database connection, connect <path> [as <alias>]
             "connect" =>{
+                let (path, alias) = match words[1..] {
+                    [path] => (path, connections::default_alias(path)),
+                    [path, "as", alias] => (path, alias.to_string()),
+                    _ => {
+                        style::print(Color::Red, Color::Reset, "Error: ")?;
+                        style::println(Color::Reset, Color::Reset, "usage: connect <path> [as <alias>]")?;
+                        continue;
+                    }
+                };
+                if connections.get(&alias).is_some() {
+                    style::print(Color::Red, Color::Reset, "Error: ")?;
+                    style::println(Color::Reset, Color::Reset, format!("a database is already connected as {}", alias).as_str())?;
+                    style::println(Color::DarkGrey, Color::Reset, "pick another name with connect <path> as <alias>")?;
+                    continue;
+                }
                 //style::println(Color::Green, Color::Reset, "connected to db")?;
-                let connection = match DataBase::create_connection(words[1]).await {
+                let connection = match DataBase::create_connection(path, true).await {
                     Ok(connection) => connection,
                     Err(_) => {
                         /* style::println(Color::Red, Color::Reset, 
@@ -297,34 +289,557 @@ once i have queried a database,
                         continue;
                     }
                 };
-                data_base.push(connection);
-                data_base_path = words[1].to_string();
-                
+                let connection = connections.add(&alias, path, connection)?;
                 
-                let db_details = match DataBase::get_database_info(data_base[data_base.len()-1].clone()).await {
+                let db_details = match schema::introduce(&connection.pool, config.schema_mode, &budget).await {
                     Ok(db_details) => db_details,
                     Err(e) => {
                         style::println(Color::Red, Color::Reset, "Error: could not get database info")?;
                         format!("Error reading database info {}", e)
                     }
                 };
-                style::println(Color::Blue, Color::Reset, &db_details)?;
-                let message = CreateMessageRequestArgs::default()
-                    .role("user")
-                    .content(format!("connected to Database >>> database info: \n {}", db_details.as_str()).as_str())
-                    .build()?;
-                let _message = client.threads().messages(&thread.id).create(message).await?;
+                backend.send_user_message(format!("connected to Database {} >>> database info: \n {}\n{} is now the active database",
+                    alias, db_details.as_str(), alias).as_str()).await?;
                 style::print(Color::Green, Color::Reset, "connected")?;
                 style::print(Color::Reset, Color::Reset, " to ")?;
-                style::println(Color::Blue, Color::Reset, &data_base_path)?;
+                style::print(Color::Blue, Color::Reset, path)?;
+                style::print(Color::Reset, Color::Reset, " as ")?;
+                style::println(Color::Blue, Color::Reset, &alias)?;
             }
-            //disconnect
+            //attach another file to the active database, attach <path> as <schema>
+            "attach" => {
+                let (path, schema) = match words[1..] {
+                    [path, "as", schema] => (path, schema),
+                    _ => {
+                        style::print(Color::Red, Color::Reset, "Error: ")?;
+                        style::println(Color::Reset, Color::Reset, "usage: attach <path> as <schema>")?;
+                        continue;
+                    }
+                };
+                let connection = match connections.active_mut() {
+                    Some(connection) => connection,
+                    None => {
+                        style::print(Color::Red, Color::Reset, "Error: ")?;
+                        style::println(Color::Reset, Color::Reset, "no database connected")?;
+                        continue;
+                    }
+                };
+                if !schema.chars().all(|c| c.is_alphanumeric() || c == '_')
+                    || ["main", "temp"].contains(&schema)
+                    || connection.attached.iter().any(|(attached, _)| attached == schema) {
+                    style::print(Color::Red, Color::Reset, "Error: ")?;
+                    style::println(Color::Reset, Color::Reset, format!("{} can not be used as a schema name", schema).as_str())?;
+                    continue;
+                }
+                // Attached on every new connection, so the path must not depend on a later cd
+                let path = match working_dir.join(path).canonicalize() {
+                    Ok(path) => path.display().to_string(),
+                    Err(_) => {
+                        style::print(Color::Red, Color::Reset, "Error: ")?;
+                        style::println(Color::Reset, Color::Reset, "could not read database")?;
+                        style::println(Color::DarkGrey, Color::Reset, "Have sure you've entered the correct path?")?;
+                        continue;
+                    }
+                };
+                let mut attached = connection.attached.clone();
+                attached.push((schema.to_string(), path.clone()));
+                let pool = match DataBase::attach(&connection.pool, &attached).await {
+                    Ok(pool) => pool,
+                    Err(e) => {
+                        style::print(Color::Red, Color::Reset, "Error: ")?;
+                        style::println(Color::Reset, Color::Reset, format!("could not attach database: {}", e).as_str())?;
+                        continue;
+                    }
+                };
+                connection.pool.close().await;
+                connection.pool = pool;
+                connection.attached = attached;
+
+                let db_details = match schema::introduce(&connection.pool, config.schema_mode, &budget).await {
+                    Ok(db_details) => db_details,
+                    Err(e) => {
+                        style::println(Color::Red, Color::Reset, "Error: could not get database info")?;
+                        format!("Error reading database info {}", e)
+                    }
+                };
+                backend.send_user_message(format!("attached {} to Database {} as schema {}, its tables are queried as {}.<table> >>> database info: \n {}",
+                    path, connection.alias, schema, schema, db_details.as_str()).as_str()).await?;
+                style::print(Color::Green, Color::Reset, "attached")?;
+                style::print(Color::Reset, Color::Reset, " ")?;
+                style::print(Color::Blue, Color::Reset, &path)?;
+                style::print(Color::Reset, Color::Reset, " as ")?;
+                style::println(Color::Blue, Color::Reset, schema)?;
+            },
+            //switch the active database
+            "use" => {
+                let alias = match words.get(1) {
+                    Some(alias) => *alias,
+                    None => {
+                        style::print(Color::Red, Color::Reset, "Error: ")?;
+                        style::println(Color::Reset, Color::Reset, "usage: use <alias>")?;
+                        continue;
+                    }
+                };
+                if let Err(e) = connections.activate(alias) {
+                    style::print(Color::Red, Color::Reset, "Error: ")?;
+                    style::println(Color::Reset, Color::Reset, &e.to_string())?;
+                    continue;
+                }
+                backend.send_user_message(format!("{} is now the active database", alias).as_str()).await?;
+                style::print(Color::Green, Color::Reset, "using ")?;
+                style::println(Color::Blue, Color::Reset, alias)?;
+            },
+            //list connected databases
+            "connections" => {
+                if connections.is_empty() {
+                    style::println(Color::DarkGrey, Color::Reset, "no database connected")?;
+                    continue;
+                }
+                let active = connections.active().map(|connection| connection.alias.clone());
+                for connection in connections.iter() {
+                    if active.as_ref() == Some(&connection.alias) {
+                        style::print(Color::Green, Color::Reset, "* ")?;
+                    } else {
+                        style::print(Color::Reset, Color::Reset, "  ")?;
+                    }
+                    style::print(Color::Blue, Color::Reset, &connection.alias)?;
+                    style::print(Color::Reset, Color::Reset, "\t")?;
+                    style::println(Color::DarkGrey, Color::Reset, &connection.path)?;
+                    for (schema, path) in &connection.attached {
+                        style::print(Color::Reset, Color::Reset, "    + ")?;
+                        style::print(Color::Blue, Color::Reset, schema)?;
+                        style::print(Color::Reset, Color::Reset, "\t")?;
+                        style::println(Color::DarkGrey, Color::Reset, path)?;
+                    }
+                }
+            },
+            //disconnect, the active database unless an alias is given
             "disconnect" => {
-                data_base.pop();
+                let alias = match (words.get(1), connections.active()) {
+                    (Some(alias), _) => alias.to_string(),
+                    (None, Some(connection)) => connection.alias.clone(),
+                    (None, None) => {
+                        style::print(Color::Red, Color::Reset, "Error: ")?;
+                        style::println(Color::Reset, Color::Reset, "no database connected")?;
+                        continue;
+                    }
+                };
+                let connection = match connections.remove(&alias) {
+                    Ok(connection) => connection,
+                    Err(e) => {
+                        style::print(Color::Red, Color::Reset, "Error: ")?;
+                        style::println(Color::Reset, Color::Reset, &e.to_string())?;
+                        continue;
+                    }
+                };
+                connection.pool.close().await;
+                let mut message = format!("disconnected from database {}", alias);
+                if let Some(active) = connections.active() {
+                    message.push_str(format!(", {} is now the active database", active.alias).as_str());
+                }
+                backend.send_user_message(&message).await?;
                 style::print(Color::Green, Color::Reset, "disconnected")?;
                 style::print(Color::Reset, Color::Reset, " from ")?;
-                style::println(Color::Blue, Color::Reset, &data_base_path)?;
-                data_base_path = String::new();
+                style::println(Color::Blue, Color::Reset, &connection.path)?;
+            },
+            //let the assistant modify the database
+            "allow-writes" => {
+                match words.get(1) {
+                    Some(&"on") => allow_writes = true,
+                    Some(&"off") => allow_writes = false,
+                    None => {},
+                    Some(_) => {
+                        style::print(Color::Red, Color::Reset, "Error: ")?;
+                        style::println(Color::Reset, Color::Reset, "usage: allow-writes [on|off]")?;
+                        continue;
+                    }
+                }
+                if allow_writes {
+                    style::print(Color::Yellow, Color::Reset, "writes allowed")?;
+                    style::println(Color::DarkGrey, Color::Reset, ", each statement that modifies the database must be confirmed")?;
+                } else {
+                    style::print(Color::Green, Color::Reset, "read-only")?;
+                    style::println(Color::DarkGrey, Color::Reset, ", statements that modify the database are rejected")?;
+                }
+            },
+            //run sql directly, lines ending in \ continue on the next line
+            "sql" => {
+                if connections.active().is_none() {
+                    style::print(Color::Red, Color::Reset, "Error: ")?;
+                    style::println(Color::Reset, Color::Reset, "no database connected")?;
+                    continue;
+                }
+                let mut query = input["sql".len()..].trim();
+                // sql --out <path> <statement> also writes the result to a file
+                let mut out = None;
+                if let Some(rest) = query.strip_prefix("--out ") {
+                    let (path, rest) = rest.trim_start().split_once(' ').unwrap_or((rest.trim(), ""));
+                    let format = match export::Format::from_path(path) {
+                        Ok(format) => format,
+                        Err(e) => {
+                            style::print(Color::Red, Color::Reset, "Error: ")?;
+                            style::println(Color::Reset, Color::Reset, &e.to_string())?;
+                            continue;
+                        }
+                    };
+                    out = Some((format, path.to_string()));
+                    query = rest.trim();
+                }
+                let query = read_continued(query);
+                if query.is_empty() {
+                    style::print(Color::Red, Color::Reset, "Error: ")?;
+                    style::println(Color::Reset, Color::Reset, "usage: sql [--out <path>] <statement>")?;
+                    continue;
+                }
+                let ctx = ToolContext {
+                    connections: &mut connections,
+                    working_dir: &working_dir,
+                    allowed_dirs: &config.allowed_dirs,
+                    schema_mode: config.schema_mode,
+                    last_result: None,
+                    budget: &budget,
+                    allow_writes,
+                    approve_queries,
+                    sql_retries: config.sql_retries,
+                    failed_queries: 0,
+                };
+                let result = match data::run_sql(&ctx, &query).await? {
+                    Some(result) => result,
+                    None => continue,
+                };
+                if share_sql {
+                    backend.send_user_message(format!("I ran this query myself: {}\nresult: {}",
+                        query, tool_output::result_set(&result, &budget)).as_str()).await?;
+                }
+                if let Some((format, path)) = out {
+                    match export::export(&result, format, &path).await {
+                        Ok(written) => {
+                            style::print(Color::Green, Color::Reset, "exported ")?;
+                            style::println(Color::Reset, Color::Reset, &written)?;
+                        },
+                        Err(e) => {
+                            style::print(Color::Red, Color::Reset, "Error: ")?;
+                            style::println(Color::Reset, Color::Reset, format!("could not export: {}", e).as_str())?;
+                        }
+                    }
+                }
+                last_result = Some(result);
+            },
+            //share the queries run with sql with the assistant
+            "share-sql" => {
+                match words.get(1) {
+                    Some(&"on") => share_sql = true,
+                    Some(&"off") => share_sql = false,
+                    None => {},
+                    Some(_) => {
+                        style::print(Color::Red, Color::Reset, "Error: ")?;
+                        style::println(Color::Reset, Color::Reset, "usage: share-sql [on|off]")?;
+                        continue;
+                    }
+                }
+                if share_sql {
+                    style::print(Color::Yellow, Color::Reset, "sharing on")?;
+                    style::println(Color::DarkGrey, Color::Reset, ", queries run with sql and their results are sent to data")?;
+                } else {
+                    style::print(Color::Green, Color::Reset, "sharing off")?;
+                    style::println(Color::DarkGrey, Color::Reset, ", queries run with sql stay between you and the database")?;
+                }
+            },
+            //list tables and views with their row counts
+            "tables" => {
+                let connection = match connections.active() {
+                    Some(connection) => connection,
+                    None => {
+                        style::print(Color::Red, Color::Reset, "Error: ")?;
+                        style::println(Color::Reset, Color::Reset, "no database connected")?;
+                        continue;
+                    }
+                };
+                let entries = match schema::list_tables(&connection.pool).await {
+                    Ok(entries) => entries,
+                    Err(e) => {
+                        style::print(Color::Red, Color::Reset, "Error: ")?;
+                        style::println(Color::Reset, Color::Reset, format!("could not list the tables: {}", e).as_str())?;
+                        continue;
+                    },
+                };
+                let width = entries.iter().map(|entry| entry.qualified_name().chars().count()).max().unwrap_or(0);
+                for entry in &entries {
+                    style::print(Color::Blue, Color::Reset, format!("{:<width$}  ", entry.qualified_name(), width = width).as_str())?;
+                    let rows = match entry.kind.as_str() {
+                        "table" => match schema::count_rows(&connection.pool, &entry.schema, &entry.name).await {
+                            Ok(rows) => rows.to_string(),
+                            Err(e) => format!("rows could not be counted: {}", e),
+                        },
+                        kind => kind.to_string(),
+                    };
+                    style::println(Color::DarkGrey, Color::Reset, format!("{} columns, {}", entry.columns.len(), rows).as_str())?;
+                }
+            },
+            //show the columns, keys and indexes of a table
+            "describe" | "schema" => {
+                let connection = match connections.active() {
+                    Some(connection) => connection,
+                    None => {
+                        style::print(Color::Red, Color::Reset, "Error: ")?;
+                        style::println(Color::Reset, Color::Reset, "no database connected")?;
+                        continue;
+                    }
+                };
+                let entries = match schema::list_tables(&connection.pool).await {
+                    Ok(entries) => entries,
+                    Err(e) => {
+                        style::print(Color::Red, Color::Reset, "Error: ")?;
+                        style::println(Color::Reset, Color::Reset, format!("could not list the tables: {}", e).as_str())?;
+                        continue;
+                    },
+                };
+                let entry = match words.get(1) {
+                    Some(name) => match schema::find_table(&entries, name) {
+                        Some(entry) => Some(entry),
+                        None => {
+                            style::print(Color::Red, Color::Reset, "Error: ")?;
+                            style::println(Color::Reset, Color::Reset, format!("no table called {}", name).as_str())?;
+                            continue;
+                        }
+                    },
+                    None if words[0] == "schema" => None,
+                    None => {
+                        style::print(Color::Red, Color::Reset, "Error: ")?;
+                        style::println(Color::Reset, Color::Reset, "usage: describe <table>")?;
+                        continue;
+                    }
+                };
+                match (words[0], entry) {
+                    //the statements that created the table, or the whole database
+                    ("schema", entry) => {
+                        let statements = match schema::create_sql(&connection.pool, entry).await {
+                            Ok(statements) => statements,
+                            Err(e) => {
+                                style::print(Color::Red, Color::Reset, "Error: ")?;
+                                style::println(Color::Reset, Color::Reset, format!("could not read the schema: {}", e).as_str())?;
+                                continue;
+                            },
+                        };
+                        for statement in statements {
+                            style::println_sql(&statement)?;
+                        }
+                    },
+                    (_, Some(entry)) if entry.kind == "view" => entry.print_view()?,
+                    (_, Some(entry)) => match entry.load(&connection.pool).await {
+                        Ok(table) => table.print()?,
+                        Err(e) => {
+                            style::print(Color::Red, Color::Reset, "Error: ")?;
+                            style::println(Color::Reset, Color::Reset, format!("could not describe {}: {}", entry.qualified_name(), e).as_str())?;
+                            continue;
+                        },
+                    },
+                    (_, None) => {},
+                }
+            },
+            //relationship diagram from the foreign keys
+            "er" => {
+                let connection = match connections.active() {
+                    Some(connection) => connection,
+                    None => {
+                        style::print(Color::Red, Color::Reset, "Error: ")?;
+                        style::println(Color::Reset, Color::Reset, "no database connected")?;
+                        continue;
+                    }
+                };
+                let (entities, relationships) = match er::load(&connection.pool, words.get(1).copied()).await {
+                    Ok(diagram) => diagram,
+                    Err(e) => {
+                        style::print(Color::Red, Color::Reset, "Error: ")?;
+                        style::println(Color::Reset, Color::Reset, format!("could not read the relationships: {}", e).as_str())?;
+                        continue;
+                    },
+                };
+                let width = match crossterm::terminal::size() {
+                    Ok((width, _)) => width as usize,
+                    Err(_) => usize::MAX,
+                };
+                er::print(&entities, &relationships, width)?;
+            },
+            //write the last result to a file, export <format> <path>
+            "export" => {
+                let (format, path) = match words[1..] {
+                    [format, path] => (format, path),
+                    _ => {
+                        style::print(Color::Red, Color::Reset, "Error: ")?;
+                        style::println(Color::Reset, Color::Reset, "usage: export <csv|json|ndjson|markdown|sqlite> <path>")?;
+                        continue;
+                    }
+                };
+                let result = match &last_result {
+                    Some(result) => result,
+                    None => {
+                        style::print(Color::Red, Color::Reset, "Error: ")?;
+                        style::println(Color::Reset, Color::Reset, "there is no result to export yet")?;
+                        continue;
+                    }
+                };
+                let written = match export::Format::parse(format) {
+                    Ok(format) => export::export(result, format, path).await,
+                    Err(e) => Err(e),
+                };
+                match written {
+                    Ok(written) => {
+                        style::print(Color::Green, Color::Reset, "exported ")?;
+                        style::println(Color::Reset, Color::Reset, &written)?;
+                    },
+                    Err(e) => {
+                        style::print(Color::Red, Color::Reset, "Error: ")?;
+                        style::println(Color::Reset, Color::Reset, format!("could not export: {}", e).as_str())?;
+                    }
+                }
+            },
+            //load a csv or json file into a table, import <file> [as <table>]
+            "import" => {
+                let (file, table) = match words[1..] {
+                    [file] => (file, import::table_name(std::path::Path::new(file))),
+                    [file, "as", table] => (file, table.to_string()),
+                    _ => {
+                        style::print(Color::Red, Color::Reset, "Error: ")?;
+                        style::println(Color::Reset, Color::Reset, "usage: import <file.csv|file.json> [as <table>]")?;
+                        continue;
+                    }
+                };
+                let result = match import::read_file(&working_dir.join(file)) {
+                    Ok(result) => result,
+                    Err(e) => {
+                        style::print(Color::Red, Color::Reset, "Error: ")?;
+                        style::println(Color::Reset, Color::Reset, format!("could not read {}: {}", file, e).as_str())?;
+                        continue;
+                    }
+                };
+                // Files go into the active database when it may be written to, into memory otherwise,
+                // which stays in the background so `sql` and `data` keep using the active database
+                let writable = connections.active()
+                    .filter(|connection| allow_writes || connection.in_memory())
+                    .map(|connection| connection.alias.clone());
+                let memory = connections.iter()
+                    .find(|connection| connection.in_memory())
+                    .map(|connection| connection.alias.clone());
+                let alias = match writable.or(memory) {
+                    Some(alias) => alias,
+                    None => {
+                        let active = connections.active().map(|connection| connection.alias.clone());
+                        let alias = connections.unique_alias("memory");
+                        let pool = DataBase::create_connection("sqlite::memory:", false).await?;
+                        connections.add(&alias, "sqlite::memory:", pool)?;
+                        if let Some(active) = active {
+                            connections.activate(&active)?;
+                        }
+                        alias
+                    },
+                };
+                let connection = connections.get(&alias).unwrap();
+                if let Err(e) = import::import(&connection.pool, &table, &result).await {
+                    style::print(Color::Red, Color::Reset, "Error: ")?;
+                    style::println(Color::Reset, Color::Reset, format!("could not import {}: {}", file, e).as_str())?;
+                    continue;
+                }
+                style::print(Color::Green, Color::Reset, "imported ")?;
+                style::print(Color::Reset, Color::Reset, format!("{} rows to table ", result.rows.len()).as_str())?;
+                style::print(Color::Blue, Color::Reset, &table)?;
+                style::print(Color::Reset, Color::Reset, " in ")?;
+                style::println(Color::Blue, Color::Reset, &connection.alias)?;
+                let active = connections.active().map(|active| active.alias.clone()).unwrap_or_default();
+                if active != connection.alias {
+                    style::println(Color::DarkGrey, Color::Reset, format!("{} is read-only and stays active, use {} to query the table", active, connection.alias).as_str())?;
+                }
+
+                let db_details = match schema::introduce(&connection.pool, config.schema_mode, &budget).await {
+                    Ok(db_details) => db_details,
+                    Err(e) => {
+                        style::println(Color::Red, Color::Reset, "Error: could not get database info")?;
+                        format!("Error reading database info {}", e)
+                    }
+                };
+                backend.send_user_message(format!("imported {} into table {} of Database {} >>> database info: \n {}\nthe active database is {}",
+                    file, table, connection.alias, db_details.as_str(), active).as_str()).await?;
+            },
+            //write the in-memory database to a file, save <path>
+            "save" => {
+                let path = match words[1..] {
+                    [path] => working_dir.join(path).display().to_string(),
+                    _ => {
+                        style::print(Color::Red, Color::Reset, "Error: ")?;
+                        style::println(Color::Reset, Color::Reset, "usage: save <path>")?;
+                        continue;
+                    }
+                };
+                let connection = match connections.active().filter(|connection| connection.in_memory()).or_else(|| connections.get("memory")) {
+                    Some(connection) => connection,
+                    None => {
+                        style::print(Color::Red, Color::Reset, "Error: ")?;
+                        style::println(Color::Reset, Color::Reset, "there is no in-memory database to save")?;
+                        continue;
+                    }
+                };
+                if std::path::Path::new(&path).exists() {
+                    style::print(Color::Red, Color::Reset, "Error: ")?;
+                    style::println(Color::Reset, Color::Reset, format!("{} already exists", path).as_str())?;
+                    continue;
+                }
+                if let Err(e) = DataBase::save(&connection.pool, &path).await {
+                    style::print(Color::Red, Color::Reset, "Error: ")?;
+                    style::println(Color::Reset, Color::Reset, format!("could not save database: {}", e).as_str())?;
+                    continue;
+                }
+                style::print(Color::Green, Color::Reset, "saved ")?;
+                style::print(Color::Blue, Color::Reset, &connection.alias)?;
+                style::print(Color::Reset, Color::Reset, " to ")?;
+                style::println(Color::Blue, Color::Reset, &path)?;
+                style::println(Color::DarkGrey, Color::Reset, "use connect to open it later")?;
+            },
+            //list the lines entered before, history [clear]
+            "history" => {
+                match words.get(1) {
+                    None if history.is_empty() => {
+                        style::println(Color::DarkGrey, Color::Reset, "no history yet")?;
+                    },
+                    None => {
+                        let width = history.len().to_string().len();
+                        for (number, entry) in history.iter().enumerate() {
+                            style::print(Color::DarkGrey, Color::Reset, format!("{:>width$}  ", number + 1, width = width).as_str())?;
+                            style::println(Color::Reset, Color::Reset, entry)?;
+                        }
+                    },
+                    Some(&"clear") => {
+                        if let Err(e) = history.clear() {
+                            style::print(Color::Red, Color::Reset, "Error: ")?;
+                            style::println(Color::Reset, Color::Reset, format!("could not clear the history: {}", e).as_str())?;
+                            continue;
+                        }
+                        style::println(Color::Green, Color::Reset, "history cleared")?;
+                    },
+                    Some(_) => {
+                        style::print(Color::Red, Color::Reset, "Error: ")?;
+                        style::println(Color::Reset, Color::Reset, "usage: history [clear]")?;
+                    }
+                }
+            },
+            //review generated queries before they run
+            "approve" => {
+                match words.get(1) {
+                    Some(&"on") => approve_queries = true,
+                    Some(&"off") => approve_queries = false,
+                    None => {},
+                    Some(_) => {
+                        style::print(Color::Red, Color::Reset, "Error: ")?;
+                        style::println(Color::Reset, Color::Reset, "usage: approve [on|off]")?;
+                        continue;
+                    }
+                }
+                if approve_queries {
+                    style::print(Color::Yellow, Color::Reset, "approval on")?;
+                    style::println(Color::DarkGrey, Color::Reset, ", queries can be run, edited or rejected before they run")?;
+                } else {
+                    style::print(Color::Green, Color::Reset, "approval off")?;
+                    style::println(Color::DarkGrey, Color::Reset, ", read-only queries run as soon as they are generated")?;
+                }
             },
             //help message
             "help" => {
@@ -333,12 +848,54 @@ once i have queried a database,
                 //data
                 style::print(Color::Magenta, Color::Reset, "\tdata:\t\t")?;
                 style::println(Color::Reset, Color::Reset, "ask data about the database")?;
+                //sql
+                style::print(Color::Magenta, Color::Reset, "\tsql:\t\t")?;
+                style::println(Color::Reset, Color::Reset, "run a statement yourself, end a line with \\ to continue it, sql [--out <path>] <statement>")?;
+                //export
+                style::print(Color::Magenta, Color::Reset, "\texport:\t\t")?;
+                style::println(Color::Reset, Color::Reset, "write the last result to a file, export <csv|json|ndjson|markdown|sqlite> <path>")?;
+                //import
+                style::print(Color::Magenta, Color::Reset, "\timport:\t\t")?;
+                style::println(Color::Reset, Color::Reset, "load a csv or json file into a table, import <file> [as <table>]")?;
+                //save
+                style::print(Color::Magenta, Color::Reset, "\tsave:\t\t")?;
+                style::println(Color::Reset, Color::Reset, "write the in-memory database to a file, save <path>")?;
+                //share-sql
+                style::print(Color::Magenta, Color::Reset, "\tshare-sql:\t")?;
+                style::println(Color::Reset, Color::Reset, "send queries run with sql and their results to data [on|off]")?;
                 //connect
                 style::print(Color::Red, Color::Reset, "\tconnect:\t")?;
-                style::println(Color::Reset, Color::Reset, "connect to a database")?;
+                style::println(Color::Reset, Color::Reset, "connect to a database, connect <path> [as <alias>]")?;
+                //tables
+                style::print(Color::Blue, Color::Reset, "\ttables:\t\t")?;
+                style::println(Color::Reset, Color::Reset, "list the tables and views with their row counts")?;
+                //describe
+                style::print(Color::Blue, Color::Reset, "\tdescribe:\t")?;
+                style::println(Color::Reset, Color::Reset, "show the columns, keys and indexes of a table, describe <table>")?;
+                //schema
+                style::print(Color::Blue, Color::Reset, "\tschema:\t\t")?;
+                style::println(Color::Reset, Color::Reset, "show the SQL that created a table, or the whole database, schema [table]")?;
+                //er
+                style::print(Color::Blue, Color::Reset, "\ter:\t\t")?;
+                style::println(Color::Reset, Color::Reset, "draw the relationships between tables, er [table]")?;
+                //attach
+                style::print(Color::Red, Color::Reset, "\tattach:\t\t")?;
+                style::println(Color::Reset, Color::Reset, "attach another file to the active database, attach <path> as <schema>")?;
+                //use
+                style::print(Color::Red, Color::Reset, "\tuse:\t\t")?;
+                style::println(Color::Reset, Color::Reset, "switch the active database, use <alias>")?;
+                //connections
+                style::print(Color::Red, Color::Reset, "\tconnections:\t")?;
+                style::println(Color::Reset, Color::Reset, "list connected databases")?;
                 //disconnect
                 style::print(Color::Yellow, Color::Reset, "\tdisconnect:\t")?;
-                style::println(Color::Reset, Color::Reset, "disconnect from a database")?;
+                style::println(Color::Reset, Color::Reset, "disconnect from a database, the active one unless an alias is given")?;
+                //allow-writes
+                style::print(Color::Yellow, Color::Reset, "\tallow-writes:\t")?;
+                style::println(Color::Reset, Color::Reset, "let data modify the database, with confirmation [on|off]")?;
+                //approve
+                style::print(Color::Green, Color::Reset, "\tapprove:\t")?;
+                style::println(Color::Reset, Color::Reset, "review each generated query before it runs [on|off]")?;
                 style::print(Color::Reset, Color::Reset, "\n")?;
                 //help
                 style::print(Color::Green, Color::Reset, "\thelp:\t\t")?;
@@ -349,6 +906,9 @@ once i have queried a database,
                 //clear
                 style::print(Color::Blue, Color::Reset, "\tclear:\t\t")?;
                 style::println(Color::Reset, Color::Reset, "clear the terminal")?;
+                //history
+                style::print(Color::Green, Color::Reset, "\thistory:\t")?;
+                style::println(Color::Reset, Color::Reset, "list the lines entered before, use up and down to bring them back [clear]")?;
                 //cd
                 style::print(Color::Magenta, Color::Reset, "\tcd:\t\t")?;
                 style::println(Color::Reset, Color::Reset, "change directory")?;
@@ -360,211 +920,35 @@ once i have queried a database,
             },
             "data" => {
                 //check if we have a connection to a database
-                if data_base.len() == 0 {
-                    style::print(Color::Red, Color::Reset, "Error: ")?;
-                    style::println(Color::Reset, Color::Reset, "no database connected")?;
-                    continue;
-                }
+                let connection = match connections.active() {
+                    Some(connection) => connection,
+                    None => {
+                        style::print(Color::Red, Color::Reset, "Error: ")?;
+                        style::println(Color::Reset, Color::Reset, "no database connected")?;
+                        continue;
+                    }
+                };
                 //println!("data");
                 style::print(Color::Magenta, Color::Reset, "Data")?;
                 style::print(Color::Reset, Color::Reset, " @ ")?;
-                style::print(Color::Blue, Color::Reset, &data_base_path)?;
+                style::print(Color::Blue, Color::Reset, &connection.path)?;
                 style::print(Color::Reset, Color::Reset, " -> ")?;
                 style::print(Color::Magenta, Color::Reset, "=")?;
-                let user_message = CreateMessageRequestArgs::default()
-                    .role("user")
-                    .content(words[1..].join(" "))
-                    .build()?;
-                let _message = client
-                    .threads()
-                    .messages(&thread.id)
-                    .create(user_message)
-                    .await?;
-
-                let run_request = CreateRunRequestArgs::default()
-                    .assistant_id(&assistant.id)
-                    .build()?;
-                let run = client.threads().runs(&thread.id).create(run_request).await?;
-
-                let mut running = true;
-                while running == true {
-                //loop {
-                    std::thread::sleep(std::time::Duration::from_secs(1));
-                    let retrieve_run = client.threads().runs(&thread.id).retrieve(&run.id).await?;
-                    match retrieve_run.status{
-                        RunStatus::InProgress => {
-                            style::print(Color::Green, Color::Reset, "=")?;
-                        },
-                        RunStatus::Completed => {
-                            style::println(Color::Green, Color::Reset, "+")?;
-                            
-                            running = false;
-
-                            //retrieve last message
-                            let last_message = client
-                                .threads()
-                                .messages(&thread.id)
-                                .list(&[("limit", "1")])
-                                .await?;
-                            let last_message_id = &last_message.data[0].id;
-                            let last_message = client
-                                .threads()
-                                .messages(&thread.id)
-                                .retrieve(&last_message_id)
-                                .await?;
-                            match last_message.content[0] {
-                                MessageContent::Text(ref text) => {
-                                    style::println(Color::Magenta, Color::Reset, &text.text.value)?;
-                                },
-                                _ => {
-                                    style::println(Color::Red, Color::Reset, "Error: last message was not text")?;
-                                }
-                            }
-                            
-                        },
-                        RunStatus::RequiresAction => {
-                            //println!("requires action");
-                            style::print(Color::Yellow, Color::Reset, "=")?;
-                            let step = client
-                                .threads()
-                                .runs(&thread.id)
-                                .steps(&run.id)
-                                .list(&[("limit", "1")])
-                                .await?;
-                            let step_id = &step.data[0].id;
-                            let step = client
-                                .threads()
-                                .runs(&thread.id)
-                                .steps(&run.id)
-                                .retrieve(&step_id)
-                                .await?;
-                            match step.step_details {
-                            
-                                StepDetails::MessageCreation(message) => {
-                                    //println!("message creation");
-                                    style::print(Color::Magenta, Color::Reset, "=")?;
-                                    let _message = client
-                                        .threads()
-                                        .messages(&thread.id)
-                                        .retrieve(&message.message_creation.message_id)
-                                        .await?;
-                                    /* for message in message.content {
-                                        match message {
-                                            MessageContent::Text(text) => {
-                                                style::println(Color::Red, Color::Reset, &text.text.value)?;
-                                            },
-                                            _ => {
-                                                style::println(Color::Red, Color::Reset, "Error: message was not text")?;
-                                            }
-                                        }
-                                    } */
-                                },
-                                StepDetails::ToolCalls(tool_calls) => {
-                                    //println!("tool calls");
-                                    style::print(Color::Red, Color::Reset, "=")?;
-                                    for tool in tool_calls.tool_calls {
-                                        match tool {
-                                            RunStepDetailsToolCalls::Function(func) => {
-                                                //println!("function {}", func.function.name.as_str());
-                                                style::print(Color::Magenta, Color::Reset, "=")?;
-                                                match func.function.name.as_str(){
-                                                    "db_query" => {
-                                                        //println!("db query");
-                                                        style::print(Color::Blue, Color::Reset, "=")?;
-                                                        let query:serde_json::Value = serde_json::from_str(&func.function.arguments.as_str())?;
-                                                        let query = match query["query"].as_str() {
-                                                            Some(query) => query,
-                                                            None => {
-                                                                style::println(Color::Red, Color::Reset, "Error: query not found")?;
-                                                                continue;
-                                                            }
-                                                        };
-                                                        //style::println(Color::Red, Color::Reset, &query)?;
-
-                                                        let query_response = match DataBase::query(
-                                                            data_base[0].clone(), 
-                                                            query.to_string(), 
-                                                            "fetch".to_string())
-                                                            .await{
-                                                                Ok(query_response) => query_response,
-                                                                Err(e) => {
-                                                                    style::println(Color::Red, Color::Reset, "Error: query failed")?;
-                                                                    style::println(Color::Red, Color::Reset, format!("{}", e).as_str())?;
-                                                                    Vec::<SqliteRow>::new()
-                                                                }
-                                                            };
-                                                            
-
-                                                        let _ = client
-                                                        .threads()
-                                                        .runs(&thread.id)
-                                                        .submit_tool_outputs(&run.id, 
-                                                            SubmitToolOutputsRunRequest{
-                                                                tool_outputs: vec![
-                                                                    ToolsOutputs{
-                                                                        tool_call_id: Some(func.id),
-                                                                        output: Some(DataBase::pretty_print_data(query_response))
-                                                                    }
-                                                                ]
-                                                            }
-                                                        ).await?;
-                                                        
-                                                    }
-                                                    "hello_world" => {
-                                                        println!("hello world");
-                                                        
-                                                        let _ = client
-                                                        .threads()
-                                                        .runs(&thread.id)
-                                                        .submit_tool_outputs(&run.id, 
-                                                            SubmitToolOutputsRunRequest{
-                                                                tool_outputs: vec![
-                                                                    ToolsOutputs{
-                                                                        tool_call_id: Some(func.id),
-                                                                        output: Some("function executed correctly".to_owned())
-                                                                    }
-                                                                ]
-                                                            }
-                                                        ).await?;
-                                                        
-                                                    }
-                                                    _=>{style::println(Color::Red, Color::Reset, "Error: assistant function not found")?;}
-                                                }
-                                            }
-                                            _=>{}
-                                        }
-                                    }
-                                },
-                            }
-                            //break;
-                        },
-                        
-                        RunStatus::Failed => {
-                            style::print(Color::Red, Color::Reset, "Error: ")?;
-                            style::println(Color::Reset, Color::Reset, "run failed")?;
-                            running = false;
-                        },
-                        RunStatus::Cancelling => {
-                            style::print(Color::Red, Color::Reset, "Error: ")?;
-                            style::println(Color::Reset, Color::Reset, "run cancelled")?;
-                            running = false;
-                        },
-                        RunStatus::Cancelled => {
-                            style::print(Color::Red, Color::Reset, "Error: ")?;
-                            style::println(Color::Reset, Color::Reset, "run cancelled")?;
-                            running = false;
-                        },
-                        RunStatus::Expired => {
-                            style::print(Color::Red, Color::Reset, "Error: ")?;
-                            style::println(Color::Reset, Color::Reset, "run expired")?;
-                            running = false;
-                        },
-                        RunStatus::Queued => {
-                            style::print(Color::Green, Color::Reset, "=")?;
-                        
-                            running = false;
-                        },
-                    };
+                let mut ctx = ToolContext {
+                    connections: &mut connections,
+                    working_dir: &working_dir,
+                    allowed_dirs: &config.allowed_dirs,
+                    schema_mode: config.schema_mode,
+                    last_result: None,
+                    budget: &budget,
+                    allow_writes,
+                    approve_queries,
+                    sql_retries: config.sql_retries,
+                    failed_queries: 0,
+                };
+                data::ask(&mut backend, &mut ctx, &words[1..].join(" ")).await?;
+                if ctx.last_result.is_some() {
+                    last_result = ctx.last_result;
                 }
             },
             _ => {
@@ -0,0 +1,879 @@
+use std::error::Error;
+
+use crossterm::style::Color;
+use serde_json::{json, Map, Value};
+use sqlx::{Pool, Row, Sqlite};
+
+use crate::config::SchemaMode;
+use crate::sql_ops::{DataBase, ResultSet, SqlValue};
+use crate::style;
+use crate::tool_output::{self, Budget};
+
+// Counting stops here, so connecting to a huge table stays fast
+const ROW_COUNT_LIMIT: i64 = 100_000;
+// Column profiles of larger tables only look at this many leading rows
+const PROFILE_ROWS: i64 = 10_000;
+const SAMPLE_ROWS: i64 = 3;
+// Text columns with at most this many distinct values get their most common values listed
+const TOP_VALUES_MAX_DISTINCT: i64 = 10;
+const TOP_VALUES: i64 = 5;
+// Longer text in samples and profiles is clipped, a long description says little about the format
+const CLIP_CHARS: usize = 40;
+// In auto mode databases with more tables than this are introduced by their table index
+const AUTO_INDEX_TABLES: usize = 30;
+
+/// Everything a query can see in a database and its attached schemas.
+/// Built from the PRAGMA introspection functions, printed for the user with `print`
+/// and sent to the assistant as compact JSON with `summary`, along with a few sample rows
+/// and column profiles so the assistant can see how values are written.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schema {
+    pub tables: Vec<TableInfo>,
+    pub views: Vec<ViewInfo>,
+    pub triggers: Vec<TriggerInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableInfo {
+    // `main` or the name of an attached database
+    pub schema: String,
+    pub name: String,
+    pub is_virtual: bool,
+    pub columns: Vec<ColumnInfo>,
+    pub foreign_keys: Vec<ForeignKey>,
+    pub indexes: Vec<IndexInfo>,
+    pub row_count: RowCount,
+    // The first rows of the table, empty for virtual tables
+    pub sample: ResultSet,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnInfo {
+    pub name: String,
+    pub type_name: String,
+    pub not_null: bool,
+    pub default: Option<String>,
+    // Position in the primary key, 0 when the column is not part of it
+    pub primary_key: i64,
+    // Generated columns are listed by `table_xinfo` but can not be inserted into
+    pub generated: bool,
+    pub profile: Option<ColumnProfile>,
+}
+
+/// Cheap statistics of a column, over at most `PROFILE_ROWS` rows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnProfile {
+    pub distinct: i64,
+    pub null_fraction: f64,
+    pub min: SqlValue,
+    pub max: SqlValue,
+    // Most common values and how often they occur, only for low-cardinality text
+    pub top_values: Vec<(SqlValue, i64)>,
+}
+
+/// How much of each table goes into the summary, from everything down to the column definitions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Detail {
+    Full,
+    Reduced,
+    Columns,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ForeignKey {
+    pub columns: Vec<String>,
+    // A foreign key can only point at a table in the schema of the table it is declared on
+    pub schema: String,
+    pub table: String,
+    // Empty when the key refers to the primary key of `table`
+    pub references: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IndexInfo {
+    pub name: String,
+    pub unique: bool,
+    pub partial: bool,
+    pub columns: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ViewInfo {
+    pub schema: String,
+    pub name: String,
+    pub sql: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TriggerInfo {
+    pub schema: String,
+    pub name: String,
+    pub table: String,
+    pub sql: String,
+}
+
+/// A table or view as listed in the table index, read from the catalog without touching any rows.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableEntry {
+    pub schema: String,
+    pub name: String,
+    // `table`, `virtual` or `view`
+    pub kind: String,
+    pub columns: Vec<(String, String)>,
+    // Columns of the primary key, in key order
+    pub primary_key: Vec<String>,
+    sql: String,
+}
+
+/// Rows in a table, counted up to `ROW_COUNT_LIMIT`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RowCount {
+    Exact(i64),
+    AtLeast(i64),
+    // Virtual tables can be expensive or impossible to count
+    Unknown,
+}
+
+impl Schema {
+    pub async fn load(db: &Pool<Sqlite>) -> Result<Schema, Box<dyn Error>> {
+        let mut schema = Schema { tables: Vec::new(), views: Vec::new(), triggers: Vec::new() };
+        for (name, _) in DataBase::schemas(db).await? {
+            let objects = sqlx::query(&format!(
+                "SELECT type, name, tbl_name, sql FROM {}.sqlite_master
+                WHERE name NOT LIKE 'sqlite_%' AND type IN ('table', 'view', 'trigger') ORDER BY rowid", quote(&name)))
+                .fetch_all(db).await?;
+            for object in objects {
+                let kind: String = object.get("type");
+                let object_name: String = object.get("name");
+                let sql: String = object.try_get::<Option<String>, &str>("sql")?.unwrap_or_default();
+                match kind.as_str() {
+                    "table" => schema.tables.push(TableInfo::load(db, &name, &object_name, &sql).await?),
+                    "view" => schema.views.push(ViewInfo { schema: name.clone(), name: object_name, sql }),
+                    _ => schema.triggers.push(TriggerInfo {
+                        schema: name.clone(),
+                        name: object_name,
+                        table: object.get("tbl_name"),
+                        sql,
+                    }),
+                }
+            }
+        }
+        Ok(schema)
+    }
+
+    /// Prints the schema for the user, one block per table followed by views and triggers.
+    pub fn print(&self) -> Result<(), Box<dyn Error>> {
+        for table in &self.tables {
+            table.print()?;
+        }
+        for view in &self.views {
+            style::print(Color::Blue, Color::Reset, &qualified(&view.schema, &view.name))?;
+            style::println(Color::DarkGrey, Color::Reset, " view")?;
+            style::println_sql(&view.sql)?;
+            style::print(Color::Reset, Color::Reset, "\n")?;
+        }
+        for trigger in &self.triggers {
+            style::print(Color::Blue, Color::Reset, &qualified(&trigger.schema, &trigger.name))?;
+            style::println(Color::DarkGrey, Color::Reset, format!(" trigger on {}", trigger.table).as_str())?;
+            style::println_sql(&trigger.sql)?;
+            style::print(Color::Reset, Color::Reset, "\n")?;
+        }
+        Ok(())
+    }
+
+    /// The schema as compact JSON for the assistant, within `budget.schema_tokens`. Tables of
+    /// attached databases are named the way queries have to refer to them, as `schema.table`.
+    ///
+    /// Samples and profiles are dropped first when the schema does not fit, then tables
+    /// from the end are only listed by name.
+    pub fn summary(&self, budget: &Budget) -> String {
+        for detail in [Detail::Full, Detail::Reduced, Detail::Columns] {
+            let summary = self.to_json(detail, self.tables.len()).to_string();
+            if budget.count_tokens(&summary) <= budget.schema_tokens {
+                return summary;
+            }
+        }
+
+        // The output only grows with the number of tables described
+        let (mut low, mut high) = (0, self.tables.len());
+        while low < high {
+            let middle = (low + high).div_ceil(2);
+            if budget.count_tokens(&self.to_json(Detail::Columns, middle).to_string()) <= budget.schema_tokens {
+                low = middle;
+            } else {
+                high = middle - 1;
+            }
+        }
+        self.to_json(Detail::Columns, low).to_string()
+    }
+
+    /// Describes the first `described` tables, the remaining ones are only named.
+    fn to_json(&self, detail: Detail, described: usize) -> Value {
+        let mut summary = Map::new();
+        summary.insert("tables".to_string(), self.tables[..described].iter().map(|table| table.to_json(detail)).collect());
+        if described < self.tables.len() {
+            summary.insert("more_tables".to_string(), self.tables[described..].iter().map(|table| json!(table.qualified_name())).collect());
+            summary.insert("note".to_string(), json!(format!("schema truncated, {} tables are only listed by name", self.tables.len() - described)));
+        }
+        if !self.views.is_empty() {
+            summary.insert("views".to_string(), self.views.iter()
+                .map(|view| json!({"name": qualified(&view.schema, &view.name), "sql": view.sql}))
+                .collect());
+        }
+        if !self.triggers.is_empty() {
+            summary.insert("triggers".to_string(), self.triggers.iter()
+                .map(|trigger| json!({"name": qualified(&trigger.schema, &trigger.name), "table": trigger.table, "sql": trigger.sql}))
+                .collect());
+        }
+        Value::Object(summary)
+    }
+}
+
+/// Prints what the user needs to know about a newly connected database and returns what the assistant
+/// is sent: the full schema summary, or only the table index when the database is too large for it.
+pub async fn introduce(db: &Pool<Sqlite>, mode: SchemaMode, budget: &Budget) -> Result<String, Box<dyn Error>> {
+    let entries = list_tables(db).await?;
+    let full = match mode {
+        SchemaMode::Full => true,
+        SchemaMode::Index => false,
+        SchemaMode::Auto => entries.len() <= AUTO_INDEX_TABLES,
+    };
+    if full {
+        let schema = DataBase::get_database_info(db.clone()).await?;
+        schema.print()?;
+        return Ok(schema.summary(budget));
+    }
+
+    style::print(Color::Blue, Color::Reset, format!("{} tables and views", entries.len()).as_str())?;
+    style::println(Color::DarkGrey, Color::Reset, ", Data will look up the ones it needs")?;
+    let names = entries.iter().map(TableEntry::qualified_name).collect::<Vec<String>>();
+    style::println(Color::DarkGrey, Color::Reset, &names.join(", "))?;
+    Ok(table_index(&entries, budget, budget.schema_tokens))
+}
+
+/// Every table and view in the database and its attached schemas, with their columns.
+pub async fn list_tables(db: &Pool<Sqlite>) -> Result<Vec<TableEntry>, Box<dyn Error>> {
+    let mut entries = Vec::new();
+    for (schema, _) in DataBase::schemas(db).await? {
+        let objects = sqlx::query(&format!(
+            "SELECT type, name, sql FROM {}.sqlite_master
+            WHERE name NOT LIKE 'sqlite_%' AND type IN ('table', 'view') ORDER BY rowid", quote(&schema)))
+            .fetch_all(db).await?;
+        for object in objects {
+            let name: String = object.get("name");
+            let sql: String = object.try_get::<Option<String>, &str>("sql")?.unwrap_or_default();
+            let kind = match object.get::<&str, &str>("type") {
+                "view" => "view",
+                _ if sql.to_uppercase().starts_with("CREATE VIRTUAL TABLE") => "virtual",
+                _ => "table",
+            };
+            let rows = sqlx::query(&format!("PRAGMA {}.table_xinfo({})", quote(&schema), quote(&name)))
+                .fetch_all(db).await
+                .unwrap_or_default();
+            let columns = rows.iter().map(|column| (column.get("name"), column.get("type"))).collect();
+            let mut primary_key = rows.iter()
+                .filter(|column| column.get::<i64, &str>("pk") > 0)
+                .map(|column| (column.get::<i64, &str>("pk"), column.get::<String, &str>("name")))
+                .collect::<Vec<(i64, String)>>();
+            primary_key.sort();
+            let primary_key = primary_key.into_iter().map(|(_, column)| column).collect();
+            entries.push(TableEntry { schema: schema.clone(), name, kind: kind.to_string(), columns, primary_key, sql });
+        }
+    }
+    Ok(entries)
+}
+
+/// The table index as compact JSON, `"users (4 columns)"` per table, within `max_tokens`.
+pub fn table_index(entries: &[TableEntry], budget: &Budget, max_tokens: usize) -> String {
+    let index = |listed: usize| {
+        let mut index = Map::new();
+        index.insert("tables".to_string(), entries[..listed].iter().map(|entry| json!(entry.to_string())).collect());
+        if listed < entries.len() {
+            index.insert("note".to_string(), json!(format!("{} more tables, call list_tables with a pattern to find them", entries.len() - listed)));
+        }
+        index.insert("hint".to_string(), json!("call describe_table for the columns, keys and sample rows of a table, search_columns to find columns by name"));
+        Value::Object(index).to_string()
+    };
+    let (mut low, mut high) = (0, entries.len());
+    while low < high {
+        let middle = (low + high).div_ceil(2);
+        if budget.count_tokens(&index(middle)) <= max_tokens {
+            low = middle;
+        } else {
+            high = middle - 1;
+        }
+    }
+    index(low)
+}
+
+/// The table or view called `name`, which may be qualified as `schema.table`.
+pub fn find_table<'a>(entries: &'a [TableEntry], name: &str) -> Option<&'a TableEntry> {
+    // Tables in main are named without their schema
+    let name = name.strip_prefix("main.").unwrap_or(name);
+    entries.iter().find(|entry| entry.qualified_name() == name)
+        .or_else(|| entries.iter().find(|entry| entry.name == name))
+}
+
+/// The full description of one table or view, within `max_tokens` where possible.
+/// Returns `None` when no table is called `name`, which may be qualified as `schema.table`.
+pub async fn describe_table(db: &Pool<Sqlite>, name: &str, budget: &Budget, max_tokens: usize) -> Result<Option<String>, Box<dyn Error>> {
+    let entries = list_tables(db).await?;
+    let entry = match find_table(&entries, name) {
+        Some(entry) => entry,
+        None => return Ok(None),
+    };
+    if entry.kind == "view" {
+        let columns = entry.columns.iter().map(|(name, type_name)| format!("{} {}", name, type_name).trim_end().to_string()).collect::<Vec<String>>();
+        return Ok(Some(json!({"name": entry.qualified_name(), "view": true, "columns": columns, "sql": entry.sql}).to_string()));
+    }
+
+    let table = entry.load(db).await?;
+    for detail in [Detail::Full, Detail::Reduced] {
+        let description = table.to_json(detail).to_string();
+        if budget.count_tokens(&description) <= max_tokens {
+            return Ok(Some(description));
+        }
+    }
+    Ok(Some(table.to_json(Detail::Columns).to_string()))
+}
+
+/// Columns whose name contains `pattern`, ignoring case, as `table.column TYPE`.
+pub fn search_columns(entries: &[TableEntry], pattern: &str) -> Vec<String> {
+    let pattern = pattern.to_lowercase();
+    entries.iter()
+        .flat_map(|entry| entry.columns.iter()
+            .filter(|(name, _)| name.to_lowercase().contains(&pattern))
+            .map(move |(name, type_name)| format!("{}.{} {}", entry.qualified_name(), name, type_name).trim_end().to_string()))
+        .collect()
+}
+
+/// The statements that created a table with its indexes and triggers, or the whole database when `table` is `None`.
+pub async fn create_sql(db: &Pool<Sqlite>, table: Option<&TableEntry>) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut statements = Vec::new();
+    for (schema, _) in DataBase::schemas(db).await? {
+        if table.is_some_and(|table| table.schema != schema) {
+            continue;
+        }
+        // Indexes created for PRIMARY KEY and UNIQUE constraints have no SQL of their own
+        let rows = sqlx::query(&format!(
+            "SELECT sql FROM {}.sqlite_master WHERE sql IS NOT NULL AND name NOT LIKE 'sqlite_%' AND (?1 IS NULL OR tbl_name = ?1)
+            ORDER BY CASE type WHEN 'table' THEN 0 WHEN 'view' THEN 1 WHEN 'index' THEN 2 ELSE 3 END, rowid", quote(&schema)))
+            .bind(table.map(|table| table.name.clone()))
+            .fetch_all(db).await?;
+        statements.extend(rows.iter().map(|row| format!("{};", row.get::<String, &str>("sql"))));
+    }
+    Ok(statements)
+}
+
+/// The foreign keys of a table, keys over several columns are combined into one.
+pub async fn foreign_keys(db: &Pool<Sqlite>, schema: &str, name: &str) -> Result<Vec<ForeignKey>, Box<dyn Error>> {
+    let mut foreign_keys: Vec<(i64, ForeignKey)> = Vec::new();
+    let rows = sqlx::query(&format!("PRAGMA {}.foreign_key_list({})", quote(schema), quote(name))).fetch_all(db).await?;
+    for row in rows {
+        let id: i64 = row.get("id");
+        let from: String = row.get("from");
+        let to: Option<String> = row.get("to");
+        // Keys over several columns come back as one row per column, sharing an id
+        match foreign_keys.iter_mut().find(|(key_id, _)| *key_id == id) {
+            Some((_, key)) => {
+                key.columns.push(from);
+                key.references.extend(to);
+            },
+            None => foreign_keys.push((id, ForeignKey {
+                columns: vec![from],
+                schema: schema.to_string(),
+                table: row.get("table"),
+                references: to.into_iter().collect(),
+            })),
+        }
+    }
+    Ok(foreign_keys.into_iter().map(|(_, key)| key).collect())
+}
+
+/// Counts the rows of a table, stopping at `ROW_COUNT_LIMIT`.
+pub async fn count_rows(db: &Pool<Sqlite>, schema: &str, name: &str) -> Result<RowCount, Box<dyn Error>> {
+    let count: i64 = sqlx::query(&format!(
+        "SELECT COUNT(*) FROM (SELECT 1 FROM {}.{} LIMIT {})", quote(schema), quote(name), ROW_COUNT_LIMIT + 1))
+        .fetch_one(db).await?
+        .get(0);
+    match count > ROW_COUNT_LIMIT {
+        true => Ok(RowCount::AtLeast(ROW_COUNT_LIMIT)),
+        false => Ok(RowCount::Exact(count)),
+    }
+}
+
+impl TableEntry {
+    pub fn qualified_name(&self) -> String {
+        qualified(&self.schema, &self.name)
+    }
+
+    /// Introspects the table in full, see `TableInfo`.
+    pub async fn load(&self, db: &Pool<Sqlite>) -> Result<TableInfo, Box<dyn Error>> {
+        TableInfo::load(db, &self.schema, &self.name, &self.sql).await
+    }
+
+    /// Prints a view for the user: its columns and the query behind it.
+    pub fn print_view(&self) -> Result<(), Box<dyn Error>> {
+        style::print(Color::Blue, Color::Reset, &self.qualified_name())?;
+        style::println(Color::DarkGrey, Color::Reset, " view")?;
+        let name_width = self.columns.iter().map(|(name, _)| name.chars().count()).max().unwrap_or(0);
+        for (name, type_name) in &self.columns {
+            style::print(Color::Reset, Color::Reset, format!("    {:<width$}  ", name, width = name_width).as_str())?;
+            style::println(Color::Yellow, Color::Reset, type_name)?;
+        }
+        style::println_sql(&self.sql)?;
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for TableEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.kind.as_str() {
+            "table" => write!(f, "{} ({} columns)", self.qualified_name(), self.columns.len()),
+            kind => write!(f, "{} ({}, {} columns)", self.qualified_name(), kind, self.columns.len()),
+        }
+    }
+}
+
+impl TableInfo {
+    async fn load(db: &Pool<Sqlite>, schema: &str, name: &str, sql: &str) -> Result<TableInfo, Box<dyn Error>> {
+        let is_virtual = sql.to_uppercase().starts_with("CREATE VIRTUAL TABLE");
+        // Virtual tables whose module is not loaded can not be introspected, they are listed without columns
+        let columns = match sqlx::query(&format!("PRAGMA {}.table_xinfo({})", quote(schema), quote(name))).fetch_all(db).await {
+            Ok(columns) => columns.iter()
+                .map(|column| ColumnInfo {
+                    name: column.get("name"),
+                    type_name: column.get("type"),
+                    not_null: column.get::<i64, &str>("notnull") != 0,
+                    default: column.get("dflt_value"),
+                    primary_key: column.get("pk"),
+                    // 2 and 3 are virtual and stored generated columns, 1 is a hidden column of a virtual table
+                    generated: column.get::<i64, &str>("hidden") >= 2,
+                    profile: None,
+                })
+                .collect::<Vec<ColumnInfo>>(),
+            Err(_) if is_virtual => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        let foreign_keys = foreign_keys(db, schema, name).await?;
+
+        let mut indexes = Vec::new();
+        let rows = sqlx::query(&format!("PRAGMA {}.index_list({})", quote(schema), quote(name))).fetch_all(db).await?;
+        for row in rows {
+            let index_name: String = row.get("name");
+            let columns = sqlx::query(&format!("PRAGMA {}.index_info({})", quote(schema), quote(&index_name)))
+                .fetch_all(db).await?
+                .iter()
+                // Indexes on expressions have no column name
+                .map(|column| column.get::<Option<String>, &str>("name").unwrap_or_else(|| "<expression>".to_string()))
+                .collect();
+            indexes.push(IndexInfo {
+                name: index_name,
+                unique: row.get::<i64, &str>("unique") != 0,
+                partial: row.get::<i64, &str>("partial") != 0,
+                columns,
+            });
+        }
+
+        let mut columns = columns;
+        let mut sample = ResultSet { columns: Vec::new(), rows: Vec::new() };
+        // Virtual tables may be backed by anything, they are not read to profile them
+        if !is_virtual {
+            for column in columns.iter_mut() {
+                column.profile = Some(ColumnProfile::load(db, schema, name, &column.name).await?);
+            }
+            let rows = sqlx::query(&format!("SELECT * FROM {}.{} LIMIT {}", quote(schema), quote(name), SAMPLE_ROWS))
+                .fetch_all(db).await?;
+            sample = ResultSet::from_rows(&rows);
+        }
+
+        let row_count = match is_virtual {
+            true => RowCount::Unknown,
+            false => count_rows(db, schema, name).await?,
+        };
+
+        Ok(TableInfo {
+            schema: schema.to_string(),
+            name: name.to_string(),
+            is_virtual,
+            columns,
+            foreign_keys,
+            indexes,
+            row_count,
+            sample,
+        })
+    }
+
+    /// The name queries use for this table.
+    pub fn qualified_name(&self) -> String {
+        qualified(&self.schema, &self.name)
+    }
+
+    /// Prints the table for the user: columns with their constraints and profiles, keys and indexes.
+    pub fn print(&self) -> Result<(), Box<dyn Error>> {
+        style::print(Color::Blue, Color::Reset, &self.qualified_name())?;
+        if self.is_virtual {
+            style::print(Color::DarkGrey, Color::Reset, " virtual")?;
+        }
+        style::println(Color::DarkGrey, Color::Reset, format!(" ({})", self.row_count).as_str())?;
+
+        let name_width = self.columns.iter().map(|column| column.name.chars().count()).max().unwrap_or(0);
+        let type_width = self.columns.iter().map(|column| column.type_name.chars().count()).max().unwrap_or(0);
+        for column in &self.columns {
+            style::print(Color::Reset, Color::Reset, format!("    {:<width$}  ", column.name, width = name_width).as_str())?;
+            style::print(Color::Yellow, Color::Reset, format!("{:<width$}", column.type_name, width = type_width).as_str())?;
+            style::print(Color::DarkGrey, Color::Reset, column.constraints().trim_end())?;
+            match &column.profile {
+                Some(profile) => style::println(Color::DarkGrey, Color::Reset, format!("  {}", profile).as_str())?,
+                None => style::print(Color::Reset, Color::Reset, "\n")?,
+            }
+        }
+        for key in &self.foreign_keys {
+            style::print(Color::Cyan, Color::Reset, "    -> ")?;
+            style::println(Color::Reset, Color::Reset, &key.to_string())?;
+        }
+        for index in &self.indexes {
+            style::print(Color::DarkGrey, Color::Reset, "    index ")?;
+            style::println(Color::DarkGrey, Color::Reset, &index.to_string())?;
+        }
+        style::print(Color::Reset, Color::Reset, "\n")?;
+        Ok(())
+    }
+
+    fn to_json(&self, detail: Detail) -> Value {
+        let mut table = Map::new();
+        table.insert("name".to_string(), json!(self.qualified_name()));
+        if self.is_virtual {
+            table.insert("virtual".to_string(), json!(true));
+        }
+        table.insert("rows".to_string(), match self.row_count {
+            RowCount::Exact(count) => json!(count),
+            count => json!(count.to_string()),
+        });
+        // Columns are written like their DDL, which is shorter than one object per column
+        table.insert("columns".to_string(), self.columns.iter()
+            .map(|column| json!(format!("{} {}{}", column.name, column.type_name, column.constraints()).trim_end()))
+            .collect());
+        if !self.foreign_keys.is_empty() {
+            table.insert("foreign_keys".to_string(), self.foreign_keys.iter().map(|key| json!(key.to_string())).collect());
+        }
+        if !self.indexes.is_empty() {
+            table.insert("indexes".to_string(), self.indexes.iter().map(|index| json!(index.to_string())).collect());
+        }
+        if detail == Detail::Columns {
+            return Value::Object(table);
+        }
+
+        let profiles = self.columns.iter()
+            .filter_map(|column| column.profile.as_ref().map(|profile| (column.name.clone(), profile.to_json(detail))))
+            .collect::<Map<String, Value>>();
+        if !profiles.is_empty() {
+            table.insert("profile".to_string(), Value::Object(profiles));
+        }
+        let sample_rows = match detail {
+            Detail::Full => SAMPLE_ROWS as usize,
+            _ => 1,
+        };
+        if !self.sample.rows.is_empty() {
+            table.insert("sample_columns".to_string(), self.sample.columns.iter().map(|column| json!(column.name)).collect());
+            table.insert("sample_rows".to_string(), self.sample.rows.iter()
+                .take(sample_rows)
+                .map(|row| Value::Array(row.iter().map(clipped).collect()))
+                .collect());
+        }
+        Value::Object(table)
+    }
+}
+
+impl ColumnInfo {
+    /// The constraints of the column as SQL, each preceded by a space.
+    fn constraints(&self) -> String {
+        let mut constraints = String::new();
+        if self.primary_key > 0 {
+            constraints.push_str(" PRIMARY KEY");
+        }
+        if self.not_null {
+            constraints.push_str(" NOT NULL");
+        }
+        if let Some(default) = &self.default {
+            constraints.push_str(format!(" DEFAULT {}", default).as_str());
+        }
+        if self.generated {
+            constraints.push_str(" GENERATED");
+        }
+        constraints
+    }
+}
+
+impl ColumnProfile {
+    async fn load(db: &Pool<Sqlite>, schema: &str, table: &str, column: &str) -> Result<ColumnProfile, Box<dyn Error>> {
+        let rows = format!("(SELECT {} AS value FROM {}.{} LIMIT {})", quote(column), quote(schema), quote(table), PROFILE_ROWS);
+        let stats = sqlx::query(&format!(
+            "SELECT COUNT(DISTINCT value), COUNT(*) - COUNT(value), COUNT(*), MIN(value), MAX(value) FROM {}", rows))
+            .fetch_one(db).await?;
+        let distinct: i64 = stats.get(0);
+        let nulls: i64 = stats.get(1);
+        let total: i64 = stats.get(2);
+        let min = SqlValue::decode(&stats, 3);
+        let max = SqlValue::decode(&stats, 4);
+
+        let mut top_values = Vec::new();
+        if distinct <= TOP_VALUES_MAX_DISTINCT && matches!(min, SqlValue::Text(_)) {
+            let rows = sqlx::query(&format!(
+                "SELECT value, COUNT(*) FROM {} WHERE value IS NOT NULL GROUP BY value ORDER BY 2 DESC, 1 LIMIT {}", rows, TOP_VALUES))
+                .fetch_all(db).await?;
+            top_values = rows.iter()
+                .map(|row| (SqlValue::decode(row, 0), row.get::<i64, usize>(1)))
+                .collect();
+        }
+
+        Ok(ColumnProfile {
+            distinct,
+            null_fraction: if total == 0 { 0.0 } else { nulls as f64 / total as f64 },
+            min,
+            max,
+            top_values,
+        })
+    }
+
+    fn to_json(&self, detail: Detail) -> Value {
+        let mut profile = Map::new();
+        profile.insert("distinct".to_string(), json!(self.distinct));
+        if self.null_fraction > 0.0 {
+            profile.insert("nulls".to_string(), json!((self.null_fraction * 100.0).round() / 100.0));
+        }
+        if !matches!(self.min, SqlValue::Null | SqlValue::Blob(_)) {
+            profile.insert("min".to_string(), clipped(&self.min));
+            profile.insert("max".to_string(), clipped(&self.max));
+        }
+        if detail == Detail::Full && !self.top_values.is_empty() {
+            profile.insert("top".to_string(), self.top_values.iter()
+                .map(|(value, count)| json!([clipped(value), count]))
+                .collect());
+        }
+        Value::Object(profile)
+    }
+}
+
+impl std::fmt::Display for ColumnProfile {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} distinct", self.distinct)?;
+        if self.null_fraction > 0.0 {
+            write!(f, ", {:.0}% null", self.null_fraction * 100.0)?;
+        }
+        if !self.top_values.is_empty() {
+            let values = self.top_values.iter()
+                .map(|(value, _)| value.to_string())
+                .collect::<Vec<String>>();
+            write!(f, ", e.g. {}", values.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl ForeignKey {
+    /// The referenced table, named the way `qualified` names tables.
+    pub fn target(&self) -> String {
+        qualified(&self.schema, &self.table)
+    }
+}
+
+impl std::fmt::Display for ForeignKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "({}) REFERENCES {}", self.columns.join(", "), self.target())?;
+        if !self.references.is_empty() {
+            write!(f, "({})", self.references.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for IndexInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.name, self.columns.join(", "))?;
+        if self.unique {
+            write!(f, " UNIQUE")?;
+        }
+        if self.partial {
+            write!(f, " PARTIAL")?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for RowCount {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RowCount::Exact(1) => write!(f, "1 row"),
+            RowCount::Exact(count) => write!(f, "{} rows", count),
+            RowCount::AtLeast(count) => write!(f, "over {} rows", count),
+            RowCount::Unknown => write!(f, "unknown rows"),
+        }
+    }
+}
+
+/// A sample or profile value for the assistant, long text is clipped.
+fn clipped(value: &SqlValue) -> Value {
+    match value {
+        SqlValue::Text(text) if text.chars().count() > CLIP_CHARS => {
+            json!(format!("{}…", text.chars().take(CLIP_CHARS).collect::<String>()))
+        },
+        value => tool_output::to_json(value),
+    }
+}
+
+/// Quotes an identifier for use in SQL.
+pub fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
+
+/// How a table is named for the user: as is in `main`, as `schema.table` elsewhere.
+pub fn qualified(schema: &str, name: &str) -> String {
+    match schema {
+        "main" => name.to_string(),
+        schema => format!("{}.{}", schema, name),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[tokio::test]
+    async fn keys_indexes_views_and_triggers_are_found() {
+        let db = fixtures::database("schema_load").await;
+        let schema = Schema::load(&db).await.unwrap();
+
+        let names = schema.tables.iter().map(|table| table.name.as_str()).collect::<Vec<&str>>();
+        assert_eq!(names, vec!["users", "orders", "events"]);
+        let users = &schema.tables[0];
+        assert_eq!(users.row_count, RowCount::Exact(3));
+        assert_eq!(users.columns[0].primary_key, 1);
+        assert!(users.columns[1].not_null);
+        assert_eq!(users.indexes[0].to_string(), "users_email (email) UNIQUE");
+        let orders = &schema.tables[1];
+        assert_eq!(orders.foreign_keys[0].to_string(), "(user_id) REFERENCES users(id)");
+        assert_eq!(orders.columns[3].default.as_deref(), Some("'pending'"));
+
+        assert_eq!(schema.views[0].name, "user_totals");
+        assert_eq!(schema.triggers[0].table, "orders");
+    }
+
+    #[tokio::test]
+    async fn names_with_quotes_are_escaped() {
+        let db = DataBase::create_connection("sqlite::memory:", false).await.unwrap();
+        sqlx::query("CREATE TABLE \"say \"\"hi\"\"\" (id INTEGER PRIMARY KEY, word TEXT); CREATE INDEX \"by \"\"word\"\"\" ON \"say \"\"hi\"\"\" (word)")
+            .execute(&db).await.unwrap();
+        let schema = Schema::load(&db).await.unwrap();
+
+        assert_eq!(schema.tables[0].name, "say \"hi\"");
+        assert_eq!(schema.tables[0].columns.len(), 2);
+        assert_eq!(schema.tables[0].indexes[0].to_string(), "by \"word\" (word)");
+    }
+
+    #[tokio::test]
+    async fn attached_foreign_keys_are_qualified() {
+        let db = fixtures::database("schema_attached").await;
+        fixtures::database("schema_attached_shop").await;
+        let both = DataBase::attach(&db, &[("shop".to_string(), fixtures::database_file("schema_attached_shop"))]).await.unwrap();
+        let schema = Schema::load(&both).await.unwrap();
+
+        let orders = schema.tables.iter().find(|table| table.schema == "shop" && table.name == "orders").unwrap();
+        assert_eq!(orders.foreign_keys[0].to_string(), "(user_id) REFERENCES shop.users(id)");
+        let summary: Value = serde_json::from_str(&schema.summary(&budget(4000))).unwrap();
+        let tables = summary["tables"].as_array().unwrap();
+        let orders = tables.iter().find(|table| table["name"] == "shop.orders").unwrap();
+        assert_eq!(orders["foreign_keys"], json!(["(user_id) REFERENCES shop.users(id)"]));
+    }
+
+    fn budget(schema_tokens: usize) -> Budget {
+        Budget::new("gpt-3.5-turbo-16k", 100, 2000, schema_tokens).unwrap()
+    }
+
+    #[tokio::test]
+    async fn columns_are_profiled() {
+        let db = fixtures::database("schema_profiles").await;
+        let schema = Schema::load(&db).await.unwrap();
+
+        let email = schema.tables[0].columns[2].profile.as_ref().unwrap();
+        assert_eq!(email.distinct, 2);
+        assert!((email.null_fraction - 1.0 / 3.0).abs() < 1e-9);
+        assert_eq!(email.min, SqlValue::Text("ada@example.com".to_string()));
+        let status = schema.tables[1].columns[3].profile.as_ref().unwrap();
+        assert_eq!(status.top_values, vec![(SqlValue::Text("shipped".to_string()), 2), (SqlValue::Text("pending".to_string()), 1)]);
+        assert_eq!(schema.tables[1].sample.rows.len(), 3);
+
+        let summary: Value = serde_json::from_str(&schema.summary(&budget(4000))).unwrap();
+        assert_eq!(summary["tables"][1]["profile"]["status"], json!({"distinct": 2, "min": "pending", "max": "shipped", "top": [["shipped", 2], ["pending", 1]]}));
+        assert_eq!(summary["tables"][0]["sample_rows"][0], json!([1, "Ada", "ada@example.com", "2023-01-04 09:12:00"]));
+    }
+
+    #[tokio::test]
+    async fn summary_shrinks_to_the_budget() {
+        let db = fixtures::database("schema_budget").await;
+        let schema = Schema::load(&db).await.unwrap();
+        let budget = budget(150);
+
+        let summary = schema.summary(&budget);
+        assert!(budget.count_tokens(&summary) <= 150);
+        let summary: Value = serde_json::from_str(&summary).unwrap();
+        assert!(summary["tables"][0].get("sample_rows").is_none());
+        assert_eq!(summary["more_tables"].as_array().unwrap().len() + summary["tables"].as_array().unwrap().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn large_databases_are_looked_up_on_demand() {
+        let db = fixtures::database("schema_lookup").await;
+        let budget = budget(4000);
+        let entries = list_tables(&db).await.unwrap();
+
+        let index: Value = serde_json::from_str(&table_index(&entries, &budget, 4000)).unwrap();
+        assert_eq!(index["tables"], json!(["users (4 columns)", "orders (5 columns)", "user_totals (view, 2 columns)", "events (6 columns)"]));
+        let index: Value = serde_json::from_str(&table_index(&entries, &budget, 60)).unwrap();
+        assert!(index["note"].as_str().unwrap().contains("more tables"));
+
+        let orders: Value = serde_json::from_str(&describe_table(&db, "orders", &budget, 2000).await.unwrap().unwrap()).unwrap();
+        assert_eq!(orders["foreign_keys"], json!(["(user_id) REFERENCES users(id)"]));
+        assert!(orders.get("sample_rows").is_some());
+        assert!(describe_table(&db, "missing", &budget, 2000).await.unwrap().is_none());
+
+        assert_eq!(search_columns(&entries, "TOTAL"), vec!["orders.total REAL", "user_totals.total"]);
+    }
+
+    #[tokio::test]
+    async fn create_sql_covers_indexes_and_triggers() {
+        let db = fixtures::database("schema_create_sql").await;
+        let entries = list_tables(&db).await.unwrap();
+
+        let statements = create_sql(&db, find_table(&entries, "orders")).await.unwrap();
+        assert!(statements[0].starts_with("CREATE TABLE orders"));
+        assert_eq!(statements[1], "CREATE INDEX orders_user ON orders (user_id);");
+        assert!(statements[2].starts_with("CREATE TRIGGER orders_placed_today"));
+        assert_eq!(create_sql(&db, None).await.unwrap().len(), 7);
+    }
+
+    #[tokio::test]
+    async fn summary_is_compact_json() {
+        let db = fixtures::database("schema_summary").await;
+        let summary: Value = serde_json::from_str(&Schema::load(&db).await.unwrap().summary(&budget(4000))).unwrap();
+        let mut orders = summary["tables"][1].clone();
+        orders.as_object_mut().unwrap().retain(|key, _| !["profile", "sample_columns", "sample_rows"].contains(&key.as_str()));
+
+        assert_eq!(orders, json!({
+            "name": "orders",
+            "rows": 3,
+            "columns": [
+                "id INTEGER PRIMARY KEY",
+                "user_id INTEGER NOT NULL",
+                "total REAL",
+                "status TEXT NOT NULL DEFAULT 'pending'",
+                "placed_at TEXT",
+            ],
+            "foreign_keys": ["(user_id) REFERENCES users(id)"],
+            "indexes": ["orders_user (user_id)"],
+        }));
+        assert_eq!(summary["views"][0]["name"], "user_totals");
+    }
+}
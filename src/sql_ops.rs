@@ -1,26 +1,266 @@
 use std::error::Error;
-use std::process::Command;
-use std::result;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
 
 use crossterm::style::Color;
-use sqlx::sqlite::{SqlitePoolOptions, SqliteColumn, SqliteRow};
-use sqlx::{prelude::*, Pool, Sqlite, Column};
-use sqlx::sqlx_macros::*;
+use sqlx::sqlite::{SqliteConnectOptions, SqliteConnection, SqlitePoolOptions, SqliteRow};
+use sqlx::{prelude::*, Pool, Sqlite, Column, Connection, TypeInfo, ValueRef};
 //use sqlx::any::*;
 
-use crate::style::{*, self};
+use crate::schema::{quote, Schema};
+use crate::style;
+use crate::table::Table;
+
+/// A single cell of a query result, decoded by the storage class of the value itself.
+/// SQLite only enforces declared column types loosely, so `VARCHAR(20)`, `DATETIME` or
+/// `NUMERIC` columns can hold any of these, and a value is never dropped for its declared type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SqlValue {
+    Null,
+    Integer(i64),
+    Real(f64),
+    Text(String),
+    Blob(Vec<u8>),
+}
+
+// Blobs longer than this are summarized instead of printed in full
+const BLOB_PREVIEW_BYTES: usize = 16;
+
+impl SqlValue {
+    pub fn decode(row: &SqliteRow, index: usize) -> SqlValue {
+        let storage_class = match row.try_get_raw(index) {
+            Ok(value) if value.is_null() => return SqlValue::Null,
+            Ok(value) => value.type_info().name().to_string(),
+            Err(_) => return SqlValue::Null,
+        };
+        let decoded = match storage_class.as_str() {
+            "INTEGER" => row.try_get_unchecked::<i64, usize>(index).map(SqlValue::Integer),
+            "REAL" => row.try_get_unchecked::<f64, usize>(index).map(SqlValue::Real),
+            "BLOB" => row.try_get_unchecked::<Vec<u8>, usize>(index).map(SqlValue::Blob),
+            _ => row.try_get_unchecked::<String, usize>(index).map(SqlValue::Text),
+        };
+        // Text that is not valid UTF-8 can still be shown as bytes
+        decoded
+            .or_else(|_| row.try_get_unchecked::<Vec<u8>, usize>(index).map(SqlValue::Blob))
+            .unwrap_or(SqlValue::Null)
+    }
+
+    /// The storage class of the value, as SQLite's `typeof()` would report it.
+    pub fn storage_class(&self) -> &'static str {
+        match self {
+            SqlValue::Null => "NULL",
+            SqlValue::Integer(_) => "INTEGER",
+            SqlValue::Real(_) => "REAL",
+            SqlValue::Text(_) => "TEXT",
+            SqlValue::Blob(_) => "BLOB",
+        }
+    }
+}
+
+impl fmt::Display for SqlValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SqlValue::Null => write!(f, "NULL"),
+            SqlValue::Integer(value) => write!(f, "{}", value),
+            // Debug formatting keeps the decimal point on whole numbers, like sqlite does
+            SqlValue::Real(value) => write!(f, "{:?}", value),
+            SqlValue::Text(value) => write!(f, "{}", value),
+            SqlValue::Blob(bytes) => {
+                let hex = bytes.iter()
+                    .take(BLOB_PREVIEW_BYTES)
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect::<String>();
+                if bytes.len() > BLOB_PREVIEW_BYTES {
+                    write!(f, "x'{}...' ({} bytes)", hex, bytes.len())
+                } else {
+                    write!(f, "x'{}'", hex)
+                }
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResultColumn {
+    pub name: String,
+    // Declared type of the column, or the storage class of its values for expressions
+    pub type_name: String,
+}
+
+/// The decoded result of a query, shared by the terminal table and the assistant's tool output.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ResultSet {
+    pub columns: Vec<ResultColumn>,
+    pub rows: Vec<Vec<SqlValue>>,
+}
+
+impl ResultSet {
+    pub fn from_rows(data: &[SqliteRow]) -> ResultSet {
+        let rows = data.iter().map(DataBase::decode_row).collect::<Vec<Vec<SqlValue>>>();
+        let columns = match data.first() {
+            Some(row) => row.columns().iter().enumerate()
+                .map(|(index, column)| {
+                    let declared = column.type_info().name().to_string();
+                    let type_name = if declared != "NULL" {
+                        declared
+                    } else {
+                        rows.iter()
+                            .map(|row| row[index].storage_class())
+                            .find(|class| *class != "NULL")
+                            .unwrap_or("NULL")
+                            .to_string()
+                    };
+                    ResultColumn {
+                        name: column.name().to_string(),
+                        type_name,
+                    }
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+        ResultSet { columns, rows }
+    }
+}
+
+/// Whether a piece of SQL can change the database.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StatementKind {
+    ReadOnly,
+    Mutating,
+}
+
+// Pragmas that only report on the database, with or without an argument
+const INTROSPECTION_PRAGMAS: [&str; 13] = [
+    "TABLE_INFO", "TABLE_XINFO", "TABLE_LIST", "INDEX_LIST", "INDEX_INFO", "INDEX_XINFO",
+    "FOREIGN_KEY_LIST", "FOREIGN_KEY_CHECK", "INTEGRITY_CHECK", "QUICK_CHECK",
+    "DATABASE_LIST", "COLLATION_LIST", "FUNCTION_LIST",
+];
+// Pragmas that are settings, only read-only when queried without a value
+const VALUE_PRAGMAS: [&str; 10] = [
+    "PAGE_COUNT", "PAGE_SIZE", "FREELIST_COUNT", "ENCODING", "USER_VERSION", "SCHEMA_VERSION",
+    "APPLICATION_ID", "JOURNAL_MODE", "FOREIGN_KEYS", "COMPILE_OPTIONS",
+];
+
+/// A token at the top level of a statement, anything inside parentheses is skipped.
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Equals,
+    OpenParen,
+}
+
+impl StatementKind {
+    /// Classifies SQL before it is run. Only SELECT, WITH ... SELECT, EXPLAIN and introspection
+    /// pragmas are read-only; anything else, including SQL that cannot be understood, is mutating.
+    /// Every statement is checked when several are separated by `;`.
+    pub fn of(sql: &str) -> StatementKind {
+        let mutating = split_statements(sql).iter().any(|tokens| {
+            let words = tokens.iter()
+                .filter_map(|token| match token {
+                    Token::Word(word) => Some(word.as_str()),
+                    _ => None,
+                })
+                .collect::<Vec<&str>>();
+            match words.first() {
+                Some(&"SELECT") | Some(&"VALUES") | Some(&"EXPLAIN") => false,
+                // A common table expression can lead into INSERT, UPDATE or DELETE
+                Some(&"WITH") => !matches!(
+                    words.iter().find(|word| matches!(**word, "SELECT" | "VALUES" | "INSERT" | "UPDATE" | "DELETE" | "REPLACE")),
+                    Some(&"SELECT") | Some(&"VALUES")
+                ),
+                Some(&"PRAGMA") => {
+                    let name = words.last().copied().unwrap_or("");
+                    let has_argument = tokens.contains(&Token::OpenParen);
+                    tokens.contains(&Token::Equals)
+                        || !(INTROSPECTION_PRAGMAS.contains(&name)
+                            || (VALUE_PRAGMAS.contains(&name) && !has_argument))
+                },
+                _ => true,
+            }
+        });
+        if mutating { StatementKind::Mutating } else { StatementKind::ReadOnly }
+    }
+
+    /// Whether SQL attaches a database file, which lets the statements after it write to that file.
+    pub fn attaches(sql: &str) -> bool {
+        split_statements(sql).iter().any(|tokens| tokens.first() == Some(&Token::Word("ATTACH".to_string())))
+    }
+}
+
+/// Splits SQL into statements of top-level tokens, skipping comments and quoted text.
+fn split_statements(sql: &str) -> Vec<Vec<Token>> {
+    let mut statements = vec![Vec::new()];
+    let mut depth = 0usize;
+    let mut chars = sql.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '-' if chars.peek() == Some(&'-') => {
+                for c in chars.by_ref() {
+                    if c == '\n' { break; }
+                }
+            },
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                for c in chars.by_ref() {
+                    if previous == '*' && c == '/' { break; }
+                    previous = c;
+                }
+            },
+            '\'' | '"' | '`' | '[' => {
+                let close = if c == '[' { ']' } else { c };
+                for c in chars.by_ref() {
+                    // Doubled quotes are escapes, they close and immediately reopen the string
+                    if c == close { break; }
+                }
+            },
+            '(' => {
+                if depth == 0 {
+                    statements.last_mut().unwrap().push(Token::OpenParen);
+                }
+                depth += 1;
+            },
+            ')' => depth = depth.saturating_sub(1),
+            '=' if depth == 0 => statements.last_mut().unwrap().push(Token::Equals),
+            ';' if depth == 0 => statements.push(Vec::new()),
+            c if c.is_alphanumeric() || c == '_' => {
+                let mut word = c.to_uppercase().to_string();
+                while let Some(&next) = chars.peek() {
+                    if !(next.is_alphanumeric() || next == '_') { break; }
+                    word.extend(next.to_uppercase());
+                    chars.next();
+                }
+                if depth == 0 {
+                    statements.last_mut().unwrap().push(Token::Word(word));
+                }
+            },
+            _ => {},
+        }
+    }
+    statements.retain(|statement| !statement.is_empty());
+    statements
+}
+
+/// A file opened by a statement such as ATTACH, named by a URI so it gets the given `mode` and a cache
+/// of its own rather than inheriting the connection's: in-memory databases are `mode=memory` with a shared cache.
+fn file_uri(path: &str, mode: &str) -> String {
+    format!("file:{}?mode={}&cache=private", path.replace('%', "%25").replace('?', "%3f").replace('#', "%23"), mode)
+}
 
 #[derive(Clone)]
 pub struct DataBase;
 
 impl DataBase{
-    pub async fn create_connection(connection:&str) -> Result<Pool<Sqlite>, sqlx::Error>{
+    /// Opens a connection pool. Pools for the user's files are opened `read_only`,
+    /// statements that change the database go through `query` with the `"execute"` type.
+    pub async fn create_connection(connection:&str, read_only:bool) -> Result<Pool<Sqlite>, sqlx::Error>{
         //sqlx::any::install_default_drivers();
 
-
-        let pool = SqlitePoolOptions::new()
-            .max_connections(5)
-            .connect(connection).await?;
+        let options = SqliteConnectOptions::from_str(connection)?
+            .read_only(read_only);
+        let pool = DataBase::pool_options()
+            .connect_with(options).await?;
 
         //run command cargo sqlx prepare
         /* let command_output = Command::new("cargo")
@@ -31,40 +271,79 @@ impl DataBase{
         Ok(pool)
     }
 
-    //returns a string of tables and columns in the database
-    //this string is formated so it can be displayed to the user or given to a bot
-    pub async fn get_database_info(db: Pool<Sqlite>) -> Result<String, Box<dyn Error>>{
-        let mut result = String::new();
-        let query = sqlx::query("SELECT name FROM sqlite_master WHERE type='table'")
-            .fetch_all(&db).await.expect("select table names failed");
-        result.push_str("TABLES\n");
-        for table in query{
-            let table_name = table.get("name");
-            result.push_str("\t");
-            result.push_str(table_name);
-            result.push_str("\n");
-            result.push_str("\t\tCOLUMNS\n");
-            let query_string = format!("PRAGMA table_info({})", table_name);
-            let columns = sqlx::query(&query_string)
-                .fetch_all(&db).await.expect("select column names failed");
-            for column in columns{
-                result.push_str("\t\t\t");
-                result.push_str(format!("{} ({})", 
-                    column.get::<&str, &str>("name"), 
-                    column.get::<&str, &str>("type")
-                ).as_str());
-                result.push_str("\n");
+    /// Reopens `db` with other database files attached under their schema names, on every
+    /// connection of the pool, so queries can join across files as `schema.table`.
+    /// `attached` holds `(schema, path)` pairs; attached files are read-only, whatever the mode of `db`,
+    /// writes to them go through `write_connection`.
+    pub async fn attach(db: &Pool<Sqlite>, attached: &[(String, String)]) -> Result<Pool<Sqlite>, Box<dyn Error>> {
+        let attached = Arc::new(attached.to_vec());
+        let options = db.connect_options().as_ref().clone();
+        let pool = DataBase::pool_options()
+            .after_connect(move |connection, _| {
+                let attached = attached.clone();
+                Box::pin(async move {
+                    for (schema, path) in attached.iter() {
+                        sqlx::query(&format!("ATTACH DATABASE ? AS {}", quote(schema)))
+                            .bind(file_uri(path, "ro"))
+                            .execute(&mut *connection).await?;
+                    }
+                    Ok(())
+                })
+            })
+            .connect_with(options).await?;
+        Ok(pool)
+    }
+
+    // An in-memory database only lives as long as a connection to it,
+    // so pools always keep one connection open
+    fn pool_options() -> SqlitePoolOptions {
+        SqlitePoolOptions::new()
+            .max_connections(5)
+            .min_connections(1)
+            .idle_timeout(None)
+            .max_lifetime(None)
+    }
+
+    /// Opens a connection that can write to the database behind `db`, even when the pool is read-only,
+    /// with the same databases attached. In-memory databases are shared, so this reaches the same data.
+    pub async fn write_connection(db: &Pool<Sqlite>) -> Result<SqliteConnection, Box<dyn Error>> {
+        let options = db.connect_options().as_ref().clone().read_only(false);
+        let mut connection = SqliteConnection::connect_with(&options).await?;
+        for (schema, file) in DataBase::schemas(db).await? {
+            if schema != "main" && !file.is_empty() {
+                sqlx::query(&format!("ATTACH DATABASE ? AS {}", quote(&schema)))
+                    .bind(file_uri(&file, "rw"))
+                    .execute(&mut connection).await?;
             }
         }
+        Ok(connection)
+    }
 
-        Ok(result)
+    /// Writes a copy of the database behind `db` to a new file at `path` with `VACUUM INTO`,
+    /// this is how an in-memory database is kept.
+    pub async fn save(db: &Pool<Sqlite>, path: &str) -> Result<(), Box<dyn Error>> {
+        sqlx::query("VACUUM INTO ?").bind(file_uri(path, "rwc")).execute(db).await?;
+        Ok(())
+    }
+
+    /// The schemas a query on `db` can see, `main` first, then every attached database.
+    pub async fn schemas(db: &Pool<Sqlite>) -> Result<Vec<(String, String)>, Box<dyn Error>> {
+        let databases = sqlx::query("PRAGMA database_list").fetch_all(db).await?;
+        Ok(databases.iter()
+            .map(|database| (database.get::<String, &str>("name"), database.get::<String, &str>("file")))
+            .filter(|(name, _)| name != "temp")
+            .collect())
+    }
+
+    /// Introspects the database and every schema attached to it.
+    /// `Schema::print` shows it to the user, `Schema::summary` is what the assistant is sent.
+    pub async fn get_database_info(db: Pool<Sqlite>) -> Result<Schema, Box<dyn Error>>{
+        Schema::load(&db).await
     }
 
     pub async fn query(db: Pool<Sqlite>, query_str:String, query_type:String) -> Result<Vec<SqliteRow>, Box<dyn Error>> {
-        style::println(
-            Color::DarkMagenta, 
-            Color::Reset, 
-            format!("\nquery: {}", query_str).as_str())?;
+        style::print(Color::DarkMagenta, Color::Reset, "\nquery: ")?;
+        style::println_sql(&query_str)?;
         //println!("query type: {}", query_type);
         let mut result = Vec::<SqliteRow>::new();
         match query_type.as_str(){
@@ -75,8 +354,11 @@ impl DataBase{
             },
             "execute" => {
                 println!("executing query ...");
-                sqlx::query(&query_str)
-                    .execute(&db).await?;
+                // The pool may be read-only, so writes get a connection of their own
+                let mut connection = DataBase::write_connection(&db).await?;
+                result = sqlx::query(&query_str)
+                    .fetch_all(&mut connection).await?;
+                connection.close().await?;
             },
             _ => {
                 println!("Invalid query type");
@@ -86,54 +368,128 @@ impl DataBase{
         Ok(result)
     }
 
-    pub fn pretty_print_data(data:Vec<SqliteRow>) -> String {
-        let mut pretty_print_columns:String = String::new();
-        let mut pretty_print:String = String::new();
-        
-        if data.len() > 0{    
-            data[0].columns().iter().for_each(|column|{
-                pretty_print_columns.push_str(format!("{} | ", column.name()).as_str());
-            });
+    /// Decodes every cell of a row, in column order.
+    pub fn decode_row(row: &SqliteRow) -> Vec<SqlValue> {
+        (0..row.columns().len())
+            .map(|index| SqlValue::decode(row, index))
+            .collect()
+    }
+
+    /// Prints query results as a table for the user.
+    /// What the assistant receives is built separately by `tool_output::result_set`.
+    pub fn pretty_print_data(data:&ResultSet) -> Result<(), Box<dyn Error>> {
+        Table::new(data).print()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fixtures;
+
+    #[tokio::test]
+    async fn values_are_decoded_by_storage_class() {
+        let db = fixtures::database("values_by_storage_class").await;
+        let rows = sqlx::query("SELECT id, code, happened, amount, payload, flag FROM events ORDER BY id DESC")
+            .fetch_all(&db).await.unwrap();
+
+        assert_eq!(DataBase::decode_row(&rows[0]), vec![
+            SqlValue::Integer(4294967296000),
+            SqlValue::Text("boot".to_string()),
+            SqlValue::Text("2023-05-01 12:00:00".to_string()),
+            SqlValue::Real(12.5),
+            SqlValue::Blob(vec![0xde, 0xad, 0xbe, 0xef]),
+            SqlValue::Integer(1),
+        ]);
+        assert_eq!(DataBase::decode_row(&rows[1]), vec![
+            SqlValue::Integer(2),
+            SqlValue::Null,
+            SqlValue::Integer(1700000000),
+            SqlValue::Integer(3),
+            SqlValue::Null,
+            SqlValue::Integer(0),
+        ]);
+    }
+
+    #[tokio::test]
+    async fn result_columns_fall_back_to_storage_class() {
+        let db = fixtures::database("result_columns").await;
+        let rows = sqlx::query("SELECT name, COUNT(*) AS orders, 1.5 * 2 AS score FROM users GROUP BY name")
+            .fetch_all(&db).await.unwrap();
+        let result = ResultSet::from_rows(&rows);
+
+        let types = result.columns.iter()
+            .map(|column| (column.name.as_str(), column.type_name.as_str()))
+            .collect::<Vec<(&str, &str)>>();
+        assert_eq!(types, vec![("name", "TEXT"), ("orders", "INTEGER"), ("score", "REAL")]);
+        assert_eq!(result.rows.len(), 3);
+    }
+
+    #[test]
+    fn statements_are_classified_before_running() {
+        for sql in [
+            "SELECT * FROM users",
+            "  -- names only\n select name from users;",
+            "WITH recent AS (SELECT * FROM orders) SELECT * FROM recent",
+            "EXPLAIN QUERY PLAN SELECT 1",
+            "PRAGMA table_info(users)",
+            "PRAGMA main.index_list('users')",
+            "PRAGMA user_version",
+            "SELECT 'DROP TABLE users; --' AS text",
+        ] {
+            assert_eq!(StatementKind::of(sql), StatementKind::ReadOnly, "{}", sql);
         }
-    
-        data.iter().for_each(|row|{
-    
-            row.columns().iter().for_each(|column|{
-                //println!("{}", column.type_info());
-                match column.type_info().to_string().as_str(){
-                    "BOOLEAN" => pretty_print.push_str(format!("{} | ", row.get::<bool, &str>(column.name())).as_str()),
-                    "INTEGER" => pretty_print.push_str(format!("{} | ", row.get::<i32, &str>(column.name())).as_str()),
-                    "REAL" => pretty_print.push_str(format!("{} | ", row.get::<f64, &str>(column.name())).as_str()),
-                    "TEXT" => pretty_print.push_str(format!("{} | ", row.get::<String, &str>(column.name())).as_str()),
-                    "BLOB" => pretty_print.push_str(format!("{} | ", "BLOB").as_str()),
-                    "NULL" => {
-                        if row.try_get::<&[u8], &str>(column.name()).is_ok(){
-                            let data = row.get::<&[u8], &str>(column.name());
-                            let data_string = String::from_utf8_lossy(data);
-                            pretty_print.push_str(format!("{} | ", data_string).as_str());
-                        }else if row.try_get::<String, &str>(column.name()).is_ok(){
-                            pretty_print.push_str(format!("{} | ", row.get::<String, &str>(column.name())).as_str());
-                        }else if row.try_get::<i32, &str>(column.name()).is_ok(){
-                            pretty_print.push_str(format!("{} | ", row.get::<i32, &str>(column.name())).as_str());
-                        }else if row.try_get::<f64, &str>(column.name()).is_ok(){
-                            pretty_print.push_str(format!("{} | ", row.get::<f64, &str>(column.name())).as_str());
-                        }else if row.try_get::<bool, &str>(column.name()).is_ok(){
-                            pretty_print.push_str(format!("{} | ", row.get::<bool, &str>(column.name())).as_str());
-                        }else{
-                            pretty_print.push_str(format!("{} | ", "NULL").as_str());
-                        }
-                    },
-                    _ => println!("Unknown type"),
-                }
-            });
-            pretty_print.push_str("\n");
-        });
-        println!("-----------------------------------");
-        println!("{}", pretty_print_columns);
-        println!("{}", pretty_print);
-        println!("-----------------------------------");
-        //join the two strings together
-        let pretty_print = format!("{}\n{}", pretty_print_columns, pretty_print);
-        pretty_print
-    }
-}
\ No newline at end of file
+        for sql in [
+            "DROP TABLE users",
+            "delete from orders",
+            "SELECT 1; DROP TABLE users",
+            "WITH old AS (SELECT id FROM orders) DELETE FROM orders WHERE id IN old",
+            "PRAGMA user_version = 3",
+            "PRAGMA journal_mode(WAL)",
+            "PRAGMA writable_schema",
+            "ATTACH 'other.db' AS other",
+            "/* SELECT */ UPDATE users SET name = 'x'",
+        ] {
+            assert_eq!(StatementKind::of(sql), StatementKind::Mutating, "{}", sql);
+        }
+    }
+
+    #[tokio::test]
+    async fn read_only_connections_refuse_writes() {
+        fixtures::database("read_only_connections").await;
+        let read_only = DataBase::create_connection(&fixtures::database_file("read_only_connections"), true)
+            .await.unwrap();
+
+        assert!(DataBase::query(read_only.clone(), "DELETE FROM orders".to_string(), "fetch".to_string()).await.is_err());
+        DataBase::query(read_only.clone(), "DELETE FROM orders".to_string(), "execute".to_string()).await.unwrap();
+        let rows = DataBase::query(read_only, "SELECT * FROM orders".to_string(), "fetch".to_string()).await.unwrap();
+        assert!(rows.is_empty());
+    }
+
+    #[tokio::test]
+    async fn attached_databases_can_be_joined() {
+        fixtures::database("attached_main").await;
+        fixtures::database("attached_other").await;
+        let db = DataBase::create_connection(&fixtures::database_file("attached_main"), true).await.unwrap();
+        let attached = vec![("other".to_string(), fixtures::database_file("attached_other"))];
+        let db = DataBase::attach(&db, &attached).await.unwrap();
+
+        let rows = DataBase::query(db.clone(),
+            "SELECT COUNT(*) FROM users JOIN other.orders ON orders.user_id = users.id".to_string(),
+            "fetch".to_string()).await.unwrap();
+        assert_eq!(rows[0].get::<i64, usize>(0), 3);
+        let info = DataBase::get_database_info(db.clone()).await.unwrap();
+        assert!(info.tables.iter().any(|table| table.qualified_name() == "other.orders"));
+        let budget = crate::tool_output::Budget::new("gpt-3.5-turbo-16k", 100, 2000, 4000).unwrap();
+        assert!(info.summary(&budget).contains("\"name\":\"other.orders\""));
+        // Attached files are as read-only as the database they are attached to
+        assert!(DataBase::query(db, "DELETE FROM other.orders".to_string(), "fetch".to_string()).await.is_err());
+    }
+
+    #[test]
+    fn blobs_are_summarized() {
+        assert_eq!(SqlValue::Blob(vec![0xde, 0xad]).to_string(), "x'dead'");
+        assert_eq!(SqlValue::Blob(vec![0; 40]).to_string(), format!("x'{}...' (40 bytes)", "00".repeat(16)));
+        assert_eq!(SqlValue::Real(120.0).to_string(), "120.0");
+    }
+}
@@ -1,26 +1,100 @@
-use std::{io::stdout, error::Error};
+use std::{io::{stdout, Write}, error::Error};
 
 use crossterm::{
-    execute,
+    queue,
     style::{Color, Print, ResetColor, SetBackgroundColor, SetForegroundColor},
-    ExecutableCommand, 
-    event,
 };
 
 
+// Written with `print!` rather than to `stdout()` directly, so the test harness captures it
 pub fn print(fg:Color, bg:Color, string:&str)->Result<(), Box<dyn Error>>{
-    execute!(
-        stdout(),
+    let mut text = Vec::new();
+    queue!(
+        text,
         SetForegroundColor(fg),
         SetBackgroundColor(bg),
         Print(string),
         ResetColor
     )?;
+    print!("{}", String::from_utf8(text)?);
+    stdout().flush()?;
     Ok(())
 }
 
 pub fn println(fg:Color, bg:Color, string:&str)->Result<(), Box<dyn Error>>{
     print(fg,bg,string)?;
-    execute!(stdout(), ResetColor, Print("\n"))?;
-    Ok(())
-}
\ No newline at end of file
+    print(Color::Reset, Color::Reset, "\n")
+}
+const SQL_KEYWORDS: [&str; 62] = [
+    "SELECT", "FROM", "WHERE", "AND", "OR", "NOT", "IN", "IS", "NULL", "AS", "ON", "USING",
+    "JOIN", "LEFT", "RIGHT", "FULL", "INNER", "OUTER", "CROSS", "NATURAL", "GROUP", "BY", "ORDER",
+    "HAVING", "LIMIT", "OFFSET", "DISTINCT", "UNION", "ALL", "EXCEPT", "INTERSECT", "ASC", "DESC",
+    "WITH", "RECURSIVE", "CASE", "WHEN", "THEN", "ELSE", "END", "LIKE", "GLOB", "BETWEEN", "EXISTS",
+    "CAST", "INSERT", "INTO", "VALUES", "UPDATE", "SET", "DELETE", "REPLACE", "CREATE", "TABLE",
+    "DROP", "ALTER", "INDEX", "VIEW", "PRAGMA", "EXPLAIN", "QUERY", "PLAN",
+];
+
+/// Splits SQL into colored spans: keywords, strings, numbers and comments each get their own color.
+/// Concatenating the spans gives back the original text.
+pub fn highlight_sql(sql: &str) -> Vec<(Color, String)> {
+    let mut spans = Vec::new();
+    let chars = sql.chars().collect::<Vec<char>>();
+    let mut index = 0;
+    while index < chars.len() {
+        let start = index;
+        let c = chars[index];
+        let color = if c == '-' && chars.get(index + 1) == Some(&'-') {
+            while index < chars.len() && chars[index] != '\n' {
+                index += 1;
+            }
+            Color::DarkGrey
+        } else if c == '\'' || c == '"' {
+            index += 1;
+            while index < chars.len() && chars[index] != c {
+                index += 1;
+            }
+            index = (index + 1).min(chars.len());
+            if c == '\'' { Color::Green } else { Color::Reset }
+        } else if c.is_ascii_digit() {
+            while index < chars.len() && (chars[index].is_ascii_alphanumeric() || chars[index] == '.') {
+                index += 1;
+            }
+            Color::Yellow
+        } else if c.is_alphanumeric() || c == '_' {
+            while index < chars.len() && (chars[index].is_alphanumeric() || chars[index] == '_') {
+                index += 1;
+            }
+            let word = chars[start..index].iter().collect::<String>().to_uppercase();
+            if SQL_KEYWORDS.contains(&word.as_str()) { Color::Magenta } else { Color::Reset }
+        } else {
+            index += 1;
+            Color::Reset
+        };
+        spans.push((color, chars[start..index].iter().collect()));
+    }
+    spans
+}
+
+pub fn println_sql(sql: &str) -> Result<(), Box<dyn Error>> {
+    for (color, text) in highlight_sql(sql) {
+        print(color, Color::Reset, &text)?;
+    }
+    println(Color::Reset, Color::Reset, "")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sql_is_highlighted_without_losing_text() {
+        let sql = "select name, 'a -- b' from users -- comment\nwhere id > 10";
+        let spans = highlight_sql(sql);
+        assert_eq!(spans.iter().map(|(_, text)| text.as_str()).collect::<String>(), sql);
+        assert!(spans.contains(&(Color::Magenta, "select".to_string())));
+        assert!(spans.contains(&(Color::Green, "'a -- b'".to_string())));
+        assert!(spans.contains(&(Color::DarkGrey, "-- comment".to_string())));
+        assert!(spans.contains(&(Color::Yellow, "10".to_string())));
+        assert!(spans.contains(&(Color::Reset, "users".to_string())));
+    }
+}
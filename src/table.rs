@@ -0,0 +1,204 @@
+use std::error::Error;
+
+use std::io::{stdout, Write};
+
+use crossterm::event::{self, Event, KeyCode};
+use crossterm::style::Color;
+use crossterm::{cursor, execute, terminal};
+
+use crate::sql_ops::{ResultSet, SqlValue};
+use crate::style;
+
+const SEPARATOR: &str = " | ";
+// Columns are never truncated below this many characters, even on tiny terminals
+const MIN_COLUMN_WIDTH: usize = 3;
+const ELLIPSIS: char = '…';
+// Lines kept free for the header, the rule and the pager prompt
+const PAGE_RESERVED_LINES: usize = 3;
+
+/// Query results laid out for the terminal: aligned columns under a header,
+/// truncated to fit the terminal width, with a row count footer.
+pub struct Table<'a> {
+    result: &'a ResultSet,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Align {
+    Left,
+    Right,
+}
+
+impl<'a> Table<'a> {
+    pub fn new(result: &'a ResultSet) -> Table<'a> {
+        Table { result }
+    }
+
+    /// Prints the table sized to the current terminal.
+    /// Results taller than the terminal are paged, so large results can still be read in full.
+    pub fn print(&self) -> Result<(), Box<dyn Error>> {
+        // Output that is not going to a terminal is never truncated or paged
+        let (terminal_width, page_size) = match terminal::size() {
+            Ok((width, height)) => (width as usize, (height as usize).saturating_sub(PAGE_RESERVED_LINES).max(1)),
+            Err(_) => (usize::MAX, usize::MAX),
+        };
+        let widths = self.layout(terminal_width);
+        let aligns = self.aligns();
+
+        if !self.result.columns.is_empty() {
+            for (index, column) in self.result.columns.iter().enumerate() {
+                if index > 0 {
+                    style::print(Color::DarkGrey, Color::Reset, SEPARATOR)?;
+                }
+                style::print(Color::Blue, Color::Reset, &pad(&column.name, widths[index], aligns[index]))?;
+            }
+            style::print(Color::Reset, Color::Reset, "\n")?;
+            let rule = widths.iter()
+                .map(|width| "-".repeat(*width))
+                .collect::<Vec<String>>()
+                .join("-+-");
+            style::println(Color::DarkGrey, Color::Reset, &rule)?;
+        }
+
+        let total = self.result.rows.len();
+        for (number, row) in self.result.rows.iter().enumerate() {
+            if number > 0 && number % page_size == 0 && !more(number, total)? {
+                break;
+            }
+            for (index, value) in row.iter().enumerate() {
+                if index > 0 {
+                    style::print(Color::DarkGrey, Color::Reset, SEPARATOR)?;
+                }
+                let color = match value {
+                    SqlValue::Null => Color::DarkGrey,
+                    _ => Color::Reset,
+                };
+                style::print(color, Color::Reset, &pad(&cell_text(value), widths[index], aligns[index]))?;
+            }
+            style::print(Color::Reset, Color::Reset, "\n")?;
+        }
+
+        let footer = match self.result.rows.len() {
+            1 => "(1 row)".to_string(),
+            count => format!("({} rows)", count),
+        };
+        style::println(Color::DarkGrey, Color::Reset, &footer)?;
+        Ok(())
+    }
+
+    /// Calculates the display width of every column so that a row fits in `max_width`.
+    /// The widest columns are narrowed first, so short columns stay readable.
+    fn layout(&self, max_width: usize) -> Vec<usize> {
+        let mut widths = self.result.columns.iter()
+            .map(|column| column.name.chars().count())
+            .collect::<Vec<usize>>();
+        for row in &self.result.rows {
+            for (index, value) in row.iter().enumerate() {
+                widths[index] = widths[index].max(cell_text(value).chars().count());
+            }
+        }
+
+        let separators = SEPARATOR.len() * widths.len().saturating_sub(1);
+        let available = max_width.saturating_sub(separators);
+        while widths.iter().sum::<usize>() > available {
+            let widest = match widths.iter().enumerate().max_by_key(|(_, width)| **width) {
+                Some((index, width)) if *width > MIN_COLUMN_WIDTH => index,
+                // Every column is already as narrow as it gets, let the terminal wrap
+                _ => break,
+            };
+            widths[widest] -= 1;
+        }
+        widths
+    }
+
+    /// Numeric columns are right aligned, everything else is left aligned.
+    fn aligns(&self) -> Vec<Align> {
+        (0..self.result.columns.len())
+            .map(|index| {
+                let mut values = self.result.rows.iter().map(|row| &row[index]).filter(|value| **value != SqlValue::Null).peekable();
+                let numeric = values.peek().is_some()
+                    && values.all(|value| matches!(value, SqlValue::Integer(_) | SqlValue::Real(_)));
+                if numeric { Align::Right } else { Align::Left }
+            })
+            .collect()
+    }
+}
+
+/// Shows the pager prompt and waits for a key, returning whether the next page should be printed.
+fn more(shown: usize, total: usize) -> Result<bool, Box<dyn Error>> {
+    style::print(Color::DarkGrey, Color::Reset,
+        format!("-- {} of {} rows, enter for more, q to stop --", shown, total).as_str())?;
+    terminal::enable_raw_mode()?;
+    let next = loop {
+        if let Event::Key(key_event) = event::read()? {
+            match key_event.code {
+                KeyCode::Enter | KeyCode::Char(' ') | KeyCode::Down => break true,
+                KeyCode::Char('q') | KeyCode::Esc => break false,
+                _ => {},
+            }
+        }
+    };
+    terminal::disable_raw_mode()?;
+    execute!(stdout(), cursor::MoveToColumn(0), terminal::Clear(terminal::ClearType::CurrentLine))?;
+    stdout().flush()?;
+    Ok(next)
+}
+
+/// The text of a cell on a single line.
+fn cell_text(value: &SqlValue) -> String {
+    value.to_string()
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect()
+}
+
+/// Pads or truncates `text` to exactly `width` characters.
+fn pad(text: &str, width: usize, align: Align) -> String {
+    let length = text.chars().count();
+    if length > width {
+        let mut truncated = text.chars().take(width.saturating_sub(1)).collect::<String>();
+        truncated.push(ELLIPSIS);
+        return truncated;
+    }
+    match align {
+        Align::Left => format!("{:<width$}", text, width = width),
+        Align::Right => format!("{:>width$}", text, width = width),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::sql_ops::ResultColumn;
+
+    fn result() -> ResultSet {
+        ResultSet {
+            columns: ["id", "name", "bio"].iter()
+                .map(|name| ResultColumn { name: name.to_string(), type_name: "TEXT".to_string() })
+                .collect(),
+            rows: vec![
+                vec![SqlValue::Integer(1), SqlValue::Text("Ada".to_string()), SqlValue::Text("wrote the first program".to_string())],
+                vec![SqlValue::Integer(20), SqlValue::Null, SqlValue::Text("short".to_string())],
+            ],
+        }
+    }
+
+    #[test]
+    fn columns_fit_their_widest_value() {
+        assert_eq!(Table::new(&result()).layout(usize::MAX), vec![2, 4, 23]);
+    }
+
+    #[test]
+    fn widest_columns_shrink_to_fit_the_terminal() {
+        // 6 characters go to the two separators
+        assert_eq!(Table::new(&result()).layout(26), vec![2, 4, 14]);
+        assert_eq!(Table::new(&result()).layout(5), vec![2, 3, 3]);
+    }
+
+    #[test]
+    fn numbers_align_right_and_text_is_truncated() {
+        assert_eq!(Table::new(&result()).aligns(), vec![Align::Right, Align::Left, Align::Left]);
+        assert_eq!(pad("20", 4, Align::Right), "  20");
+        assert_eq!(pad("wrote the first program", 8, Align::Left), "wrote t…");
+    }
+}
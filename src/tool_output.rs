@@ -0,0 +1,205 @@
+use std::collections::HashSet;
+use std::error::Error;
+
+use serde_json::{json, Map, Value};
+use tiktoken_rs::CoreBPE;
+
+use crate::sql_ops::{ResultSet, SqlValue};
+
+/// Limits on how much of a result set is sent back to the assistant for one tool call,
+/// and on the size of the schema summary sent when a database is connected.
+/// Tokens are counted with the tokenizer of the selected model.
+pub struct Budget {
+    pub max_rows: usize,
+    pub max_tokens: usize,
+    pub schema_tokens: usize,
+    bpe: CoreBPE,
+}
+
+impl Budget {
+    pub fn new(model: &str, max_rows: usize, max_tokens: usize, schema_tokens: usize) -> Result<Budget, Box<dyn Error>> {
+        // Models tiktoken does not know about (local servers) are counted as cl100k
+        let bpe = match tiktoken_rs::get_bpe_from_model(model) {
+            Ok(bpe) => bpe,
+            Err(_) => tiktoken_rs::cl100k_base()?,
+        };
+        Ok(Budget { max_rows, max_tokens, schema_tokens, bpe })
+    }
+
+    pub fn count_tokens(&self, text: &str) -> usize {
+        self.bpe.encode_with_special_tokens(text).len()
+    }
+}
+
+/// Serializes query results for the assistant as compact JSON, independent of how they are shown
+/// to the user. As many leading rows as fit in the budget are included; `row_count` is always the
+/// size of the full result, and a truncated result carries a note and per-column statistics.
+///
+/// `{"columns":[{"name":"id","type":"INTEGER"}],"rows":[[1]],"row_count":1,"truncated":false}`
+pub fn result_set(result: &ResultSet, budget: &Budget) -> String {
+    let columns = result.columns.iter()
+        .map(|column| json!({"name": column.name, "type": column.type_name}))
+        .collect::<Vec<Value>>();
+    let rows = result.rows.iter()
+        .take(budget.max_rows)
+        .map(|row| Value::Array(row.iter().map(to_json).collect()))
+        .collect::<Vec<Value>>();
+
+    let mut output = serialize(&columns, &rows, result);
+    if rows.len() == result.rows.len() && budget.count_tokens(&output) <= budget.max_tokens {
+        return output;
+    }
+
+    // Find the largest sample that still fits, the output only grows with the number of rows
+    let summary = summarize(result);
+    let (mut low, mut high) = (0, rows.len());
+    while low < high {
+        let middle = (low + high).div_ceil(2);
+        let candidate = truncated(&columns, &rows[..middle], result, &summary);
+        if budget.count_tokens(&candidate) <= budget.max_tokens {
+            low = middle;
+            output = candidate;
+        } else {
+            high = middle - 1;
+        }
+    }
+    if low == 0 {
+        output = truncated(&columns, &[], result, &summary);
+    }
+    output
+}
+
+/// The error of a failed query, so the assistant can correct the query and run it again
+/// while `retries_left` is above zero.
+///
+/// `{"error":"no such column: nme","code":"1","query":"SELECT nme FROM users","retries_left":1}`
+pub fn query_error(query: &str, code: Option<&str>, message: &str, retries_left: usize) -> String {
+    let mut error = json!({
+        "error": message,
+        "code": code,
+        "query": query,
+        "retries_left": retries_left,
+    });
+    if retries_left == 0 {
+        error["note"] = json!("no retries left, do not run the query again, explain the error to the user");
+    }
+    error.to_string()
+}
+
+fn serialize(columns: &[Value], rows: &[Value], result: &ResultSet) -> String {
+    json!({
+        "columns": columns,
+        "rows": rows,
+        "row_count": result.rows.len(),
+        "truncated": false,
+    }).to_string()
+}
+
+fn truncated(columns: &[Value], rows: &[Value], result: &ResultSet, summary: &Value) -> String {
+    json!({
+        "columns": columns,
+        "rows": rows,
+        "row_count": result.rows.len(),
+        "truncated": true,
+        "note": format!("truncated, {} more rows", result.rows.len() - rows.len()),
+        "summary": summary,
+    }).to_string()
+}
+
+/// Statistics over the full result, so the model can reason about rows it did not see.
+fn summarize(result: &ResultSet) -> Value {
+    let mut summary = Map::new();
+    for (index, column) in result.columns.iter().enumerate() {
+        let values = result.rows.iter()
+            .map(|row| &row[index])
+            .filter(|value| **value != SqlValue::Null)
+            .collect::<Vec<&SqlValue>>();
+        let mut stats = Map::new();
+        stats.insert("nulls".to_string(), json!(result.rows.len() - values.len()));
+
+        let numbers = values.iter()
+            .filter_map(|value| match value {
+                SqlValue::Integer(number) => Some(*number as f64),
+                SqlValue::Real(number) => Some(*number),
+                _ => None,
+            })
+            .collect::<Vec<f64>>();
+        if !numbers.is_empty() && numbers.len() == values.len() {
+            stats.insert("min".to_string(), json!(numbers.iter().cloned().fold(f64::INFINITY, f64::min)));
+            stats.insert("max".to_string(), json!(numbers.iter().cloned().fold(f64::NEG_INFINITY, f64::max)));
+            stats.insert("mean".to_string(), json!(numbers.iter().sum::<f64>() / numbers.len() as f64));
+        } else {
+            let distinct = values.iter().map(|value| value.to_string()).collect::<HashSet<String>>();
+            stats.insert("distinct".to_string(), json!(distinct.len()));
+        }
+        summary.insert(column.name.clone(), Value::Object(stats));
+    }
+    Value::Object(summary)
+}
+
+pub fn to_json(value: &SqlValue) -> Value {
+    match value {
+        SqlValue::Null => Value::Null,
+        SqlValue::Integer(value) => json!(value),
+        // NaN and infinities have no JSON representation and become null
+        SqlValue::Real(value) => json!(value),
+        SqlValue::Text(value) => json!(value),
+        SqlValue::Blob(_) => json!(value.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sql_ops::ResultColumn;
+
+    fn result(rows: usize) -> ResultSet {
+        ResultSet {
+            columns: vec![
+                ResultColumn { name: "id".to_string(), type_name: "INTEGER".to_string() },
+                ResultColumn { name: "note".to_string(), type_name: "TEXT".to_string() },
+            ],
+            rows: (0..rows)
+                .map(|id| vec![
+                    SqlValue::Integer(id as i64),
+                    if id % 2 == 0 { SqlValue::Null } else { SqlValue::Text(format!("note {}", id % 5)) },
+                ])
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn small_results_are_sent_whole() {
+        let budget = Budget::new("gpt-3.5-turbo-16k", 100, 1000, 4000).unwrap();
+        let output: Value = serde_json::from_str(&result_set(&result(2), &budget)).unwrap();
+        assert_eq!(output, json!({
+            "columns": [{"name": "id", "type": "INTEGER"}, {"name": "note", "type": "TEXT"}],
+            "rows": [[0, null], [1, "note 1"]],
+            "row_count": 2,
+            "truncated": false,
+        }));
+    }
+
+    #[test]
+    fn row_limit_truncates_with_a_summary() {
+        let budget = Budget::new("gpt-3.5-turbo-16k", 3, 1000, 4000).unwrap();
+        let output: Value = serde_json::from_str(&result_set(&result(10), &budget)).unwrap();
+        assert_eq!(output["rows"].as_array().unwrap().len(), 3);
+        assert_eq!(output["row_count"], 10);
+        assert_eq!(output["truncated"], true);
+        assert_eq!(output["note"], "truncated, 7 more rows");
+        assert_eq!(output["summary"]["id"], json!({"nulls": 0, "min": 0.0, "max": 9.0, "mean": 4.5}));
+        assert_eq!(output["summary"]["note"], json!({"nulls": 5, "distinct": 5}));
+    }
+
+    #[test]
+    fn token_budget_is_respected() {
+        let budget = Budget::new("some-local-model", 1000, 300, 4000).unwrap();
+        let output = result_set(&result(1000), &budget);
+        assert!(budget.count_tokens(&output) <= 300);
+        let output: Value = serde_json::from_str(&output).unwrap();
+        let sent = output["rows"].as_array().unwrap().len();
+        assert!(sent > 0 && sent < 1000);
+        assert_eq!(output["note"], format!("truncated, {} more rows", 1000 - sent));
+    }
+}
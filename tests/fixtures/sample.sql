@@ -0,0 +1,48 @@
+CREATE TABLE users (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL,
+    email TEXT,
+    signup DATETIME
+);
+
+CREATE TABLE orders (
+    id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id),
+    total REAL,
+    status TEXT NOT NULL DEFAULT 'pending',
+    placed_at TEXT
+);
+
+CREATE UNIQUE INDEX users_email ON users (email);
+CREATE INDEX orders_user ON orders (user_id);
+
+CREATE VIEW user_totals AS
+    SELECT users.name, SUM(orders.total) AS total FROM users JOIN orders ON orders.user_id = users.id GROUP BY users.id;
+
+CREATE TRIGGER orders_placed_today AFTER INSERT ON orders WHEN NEW.placed_at IS NULL
+BEGIN
+    UPDATE orders SET placed_at = date('now') WHERE id = NEW.id;
+END;
+
+INSERT INTO users (id, name, email, signup) VALUES
+    (1, 'Ada', 'ada@example.com', '2023-01-04 09:12:00'),
+    (2, 'Grace', 'grace@example.com', '2023-02-11 17:40:00'),
+    (3, 'Linus', NULL, '2023-03-19 08:05:00');
+
+INSERT INTO orders (id, user_id, total, status, placed_at) VALUES
+    (1, 1, 19.99, 'shipped', '2023-04-01'),
+    (2, 1, 5.25, 'shipped', '2023-04-03'),
+    (3, 2, 120.0, 'pending', '2023-04-07');
+
+CREATE TABLE events (
+    id INTEGER PRIMARY KEY,
+    code VARCHAR(20),
+    happened DATETIME,
+    amount NUMERIC,
+    payload BLOB,
+    flag BOOLEAN
+);
+
+INSERT INTO events (id, code, happened, amount, payload, flag) VALUES
+    (4294967296000, 'boot', '2023-05-01 12:00:00', 12.5, x'deadbeef', 1),
+    (2, NULL, 1700000000, 3, NULL, 0);